@@ -0,0 +1,361 @@
+//! Blocking clients based on SRV lookups, for use in synchronous code.
+//!
+//! A blocking [`SrvClient`] wraps an asynchronous [`crate::SrvClient`],
+//! sharing its resolver, [`Policy`], cache, and configuration, but performs
+//! operations with plain (non-`async`) closures, one target at a time, without
+//! requiring an async runtime. Note that the client's resolver must itself be
+//! usable without a runtime: [`LibResolv`] and [`StaticResolver`] are, but
+//! [`hickory_resolver::Resolver`] requires one.
+//!
+//! # Timeouts
+//!
+//! Since operations are plain closures, an attempt cannot be abandoned while
+//! it runs: the client's [attempt timeout](crate::SrvClient::attempt_timeout)
+//! has no effect, and an attempt is only ever ended by its closure returning.
+//! Operations that must not block indefinitely should enforce their own
+//! timeouts (e.g. with [`TcpStream::connect_timeout`]), optionally alongside
+//! an overall [`timeout`](SrvClient::timeout), after which no further targets
+//! are tried.
+//!
+//! [`TcpStream::connect_timeout`]: std::net::TcpStream::connect_timeout
+//! [`Policy`]: crate::policy::Policy
+//! [`LibResolv`]: crate::resolver::libresolv::LibResolv
+//! [`StaticResolver`]: crate::resolver::manual::StaticResolver
+
+use super::{
+    AttemptContext, AttemptError, CallOptions, Error, Execution, IntoOutcome, Retry, Target, policy,
+};
+use crate::resolver::{SrvResolver, manual::StaticResolver};
+use futures_executor::block_on;
+use futures_util::future;
+use http::Uri;
+use std::{
+    fmt::{self, Debug},
+    time::{Duration, Instant},
+};
+
+/// Blocking client for intelligently performing operations on a service
+/// located by SRV records. See the [module documentation](self).
+///
+/// # Examples
+///
+/// ```
+/// use srv_rs::blocking::SrvClient;
+/// use srv_rs::resolver::manual::StaticResolver;
+///
+/// let resolver = StaticResolver::new_from_single_target("server.example.com", 8080);
+/// let client: SrvClient<_> = SrvClient::new_with_static_resolver(resolver);
+/// let res = client.execute(|address| Ok::<_, std::io::Error>(address.to_string()));
+/// assert_eq!(res.unwrap().unwrap(), "https://server.example.com:8080/");
+/// ```
+pub struct SrvClient<Resolver, Policy: policy::Policy = policy::Affinity> {
+    inner: super::SrvClient<Resolver, Policy>,
+    timeout: Option<Duration>,
+}
+
+impl<Resolver: Default, Policy: policy::Policy + Default> SrvClient<Resolver, Policy> {
+    /// Creates a new client for communicating with services located by `srv_name`,
+    /// using a default-constructed resolver.
+    pub fn new(srv_name: impl Into<String>) -> Self {
+        super::SrvClient::new(srv_name).into()
+    }
+}
+
+impl<Resolver, Policy: policy::Policy + Default> SrvClient<Resolver, Policy> {
+    /// Creates a new client for communicating with services located by `srv_name`.
+    pub fn new_with_resolver(srv_name: impl Into<String>, resolver: Resolver) -> Self {
+        super::SrvClient::new_with_resolver(srv_name, resolver).into()
+    }
+}
+
+impl<Policy: policy::Policy + Default> SrvClient<StaticResolver, Policy> {
+    /// Creates a new client using a [`StaticResolver`], bypassing SRV DNS resolution.
+    #[must_use]
+    pub fn new_with_static_resolver(resolver: StaticResolver) -> Self {
+        super::SrvClient::new_with_static_resolver(resolver).into()
+    }
+}
+
+impl<Resolver: SrvResolver, Policy: policy::Policy> SrvClient<Resolver, Policy> {
+    /// Performs an operation on a client's SRV targets one at a time, in the
+    /// order determined by the current [`Policy`], producing the first
+    /// successful result or the last error encountered if every execution of
+    /// the operation was unsuccessful. See [`crate::SrvClient::execute`].
+    ///
+    /// Each attempt runs until `func` returns: attempts are not abandoned
+    /// after the client's attempt timeout (see the [module documentation](self)).
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::Lookup`] if DNS resolution fails
+    /// - [`Error::RecordParsing`] if SRV records cannot be parsed into valid URIs
+    /// - [`Error::NoTargets`] if there are no SRV targets available
+    /// - [`Error::DeadlineExceeded`] if the client's [`timeout`] passes before
+    ///   any attempt succeeds
+    ///
+    /// [`Policy`]: policy::Policy
+    /// [`timeout`]: SrvClient::timeout()
+    pub fn execute<T, E, R>(
+        &self,
+        func: impl FnMut(Uri) -> R,
+    ) -> Result<Result<T, AttemptError<E>>, Error<Resolver::Error>>
+    where
+        E: std::error::Error,
+        R: IntoOutcome<Ok = T, Error = E>,
+    {
+        self.inner.check_path_prefix()?;
+        self.execute_projected(AttemptContext::into_uri, func)
+    }
+
+    /// Performs an operation on a client's SRV targets like [`execute`], but
+    /// passes the operation the host and port of each target as a [`Target`]
+    /// rather than a URI. See [`crate::SrvClient::execute_target`].
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::Lookup`] if DNS resolution fails
+    /// - [`Error::RecordParsing`] if SRV records cannot be parsed into valid URIs
+    /// - [`Error::NoTargets`] if there are no SRV targets available
+    /// - [`Error::DeadlineExceeded`] if the client's [`timeout`] passes before
+    ///   any attempt succeeds
+    ///
+    /// [`execute`]: SrvClient::execute()
+    /// [`timeout`]: SrvClient::timeout()
+    pub fn execute_target<T, E, R>(
+        &self,
+        func: impl FnMut(Target) -> R,
+    ) -> Result<Result<T, AttemptError<E>>, Error<Resolver::Error>>
+    where
+        E: std::error::Error,
+        R: IntoOutcome<Ok = T, Error = E>,
+    {
+        self.execute_projected(|context| context.target(), func)
+    }
+
+    /// Performs an operation on every one of a client's SRV targets one at a
+    /// time, producing the result of each attempt along with its target. See
+    /// [`crate::SrvClient::execute_all`].
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::Lookup`] if DNS resolution fails
+    /// - [`Error::RecordParsing`] if SRV records cannot be parsed into valid URIs
+    ///
+    /// Every target is tried, regardless of the client's [`timeout`].
+    ///
+    /// [`timeout`]: SrvClient::timeout()
+    #[allow(clippy::type_complexity)]
+    pub fn execute_all<T, E, R>(
+        &self,
+        mut func: impl FnMut(Uri) -> R,
+    ) -> Result<Vec<(Uri, Result<T, AttemptError<E>>)>, Error<Resolver::Error>>
+    where
+        E: std::error::Error,
+        R: IntoOutcome<Ok = T, Error = E>,
+    {
+        let func = |address| future::ready(func(address));
+        block_on(self.inner.execute_all(Execution::Serial, func))
+    }
+
+    /// Performs an operation serially, passing it the part of each attempt's
+    /// context picked by `project` and giving up on further targets once the
+    /// client's timeout passes.
+    fn execute_projected<A, T, E, R>(
+        &self,
+        project: fn(AttemptContext) -> A,
+        mut func: impl FnMut(A) -> R,
+    ) -> Result<Result<T, AttemptError<E>>, Error<Resolver::Error>>
+    where
+        E: std::error::Error,
+        R: IntoOutcome<Ok = T, Error = E>,
+    {
+        let options = CallOptions::new();
+        // A timeout too long to represent as a deadline is no timeout at all
+        let deadline = self
+            .timeout
+            .and_then(|timeout| Instant::now().checked_add(timeout));
+        let options = deadline.map_or(options, |deadline| options.deadline(deadline));
+        let func = |arg| future::ready(func(arg));
+        block_on(
+            self.inner
+                .execute_projected(Execution::Serial, options, project, func, |_| {}),
+        )
+    }
+}
+
+impl<Resolver, Policy: policy::Policy> SrvClient<Resolver, Policy> {
+    /// Sets the policy of the client. See [`crate::SrvClient::policy`].
+    pub fn policy<P: policy::Policy>(self, policy: P) -> SrvClient<Resolver, P> {
+        SrvClient {
+            inner: self.inner.policy(policy),
+            timeout: self.timeout,
+        }
+    }
+
+    /// Sets the retry configuration of the client, which determines how
+    /// [`execute`] behaves when every SRV target fails. See
+    /// [`crate::SrvClient::retry`].
+    ///
+    /// [`execute`]: SrvClient::execute()
+    #[must_use]
+    pub fn retry(self, retry: Retry) -> Self {
+        Self {
+            inner: self.inner.retry(retry),
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of targets the client tries in each round of
+    /// performing an operation. See [`crate::SrvClient::max_attempts`].
+    #[must_use]
+    pub fn max_attempts(self, max_attempts: usize) -> Self {
+        Self {
+            inner: self.inner.max_attempts(max_attempts),
+            ..self
+        }
+    }
+
+    /// Sets the maximum time an operation may take, including the SRV lookup
+    /// and any retries. No further targets are tried once it has passed, but
+    /// an attempt already running is not interrupted (see the
+    /// [module documentation](self)). By default, operations have no timeout.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use srv_rs::blocking::SrvClient;
+    /// use srv_rs::resolver::manual::StaticResolver;
+    /// use std::time::Duration;
+    ///
+    /// let resolver = StaticResolver::new_from_single_target("server.example.com", 8080);
+    /// let client: SrvClient<_> =
+    ///     SrvClient::new_with_static_resolver(resolver).timeout(Duration::from_secs(5));
+    /// ```
+    #[must_use]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Gets the asynchronous client wrapped by a blocking client.
+    pub const fn get_ref(&self) -> &super::SrvClient<Resolver, Policy> {
+        &self.inner
+    }
+
+    /// Converts into the asynchronous client wrapped by a blocking client.
+    pub fn into_inner(self) -> super::SrvClient<Resolver, Policy> {
+        self.inner
+    }
+}
+
+impl<Resolver, Policy: policy::Policy> Debug for SrvClient<Resolver, Policy>
+where
+    super::SrvClient<Resolver, Policy>: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SrvClient")
+            .field("inner", &self.inner)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl<Resolver, Policy: policy::Policy> From<super::SrvClient<Resolver, Policy>>
+    for SrvClient<Resolver, Policy>
+{
+    fn from(inner: super::SrvClient<Resolver, Policy>) -> Self {
+        Self {
+            inner,
+            timeout: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Outcome;
+
+    fn client() -> SrvClient<StaticResolver, policy::Rfc2782> {
+        super::super::tests::client().into()
+    }
+
+    #[test]
+    fn execute_fails_over_serially() {
+        let mut tried = Vec::new();
+        let res = client().execute(|address| {
+            tried.push(address.host().unwrap().to_owned());
+            match tried.len() {
+                3 => Outcome::Success(tried.len()),
+                _ => Outcome::Failure(std::io::Error::other("unavailable")),
+            }
+        });
+        assert_eq!(res.unwrap().unwrap(), 3);
+        assert_eq!(
+            tried,
+            [
+                "server0.example.com",
+                "server1.example.com",
+                "server2.example.com"
+            ]
+        );
+    }
+
+    #[test]
+    fn execute_target_passes_host_and_port() {
+        let res = client().execute_target(|target| match target.host() {
+            "server0.example.com" => Err(std::io::Error::other("unavailable")),
+            _ => Ok(target),
+        });
+        assert_eq!(
+            res.unwrap().unwrap(),
+            Target::new("server1.example.com", 8080)
+        );
+    }
+
+    #[test]
+    fn execute_all_tries_every_target() {
+        let results = client()
+            .execute_all(|address| Ok::<_, std::io::Error>(address.port_u16()))
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|(_, res)| matches!(res, Ok(Some(8080)))));
+    }
+
+    #[test]
+    fn timeout_stops_failover() {
+        let client = client().max_attempts(2).timeout(Duration::from_millis(20));
+        let mut tried = 0;
+        let res = client.execute(|_| {
+            tried += 1;
+            std::thread::sleep(Duration::from_millis(30));
+            Err::<(), _>(std::io::Error::other("unavailable"))
+        });
+        // The first attempt runs to completion, but no further targets are tried
+        assert!(matches!(res, Err(Error::DeadlineExceeded { attempted: 1 })));
+        assert_eq!(tried, 1);
+    }
+
+    #[test]
+    fn huge_timeout_is_no_timeout() {
+        let res = client()
+            .timeout(Duration::MAX)
+            .execute(|_| Ok::<_, std::io::Error>(()));
+        assert!(matches!(res, Ok(Ok(()))));
+    }
+
+    #[test]
+    fn retry_performs_further_rounds() {
+        let retry = Retry::new(2).backoff(Duration::ZERO, Duration::ZERO);
+        let client = client().max_attempts(1).retry(retry);
+        let mut tried = 0;
+        let res = client.execute(|_| {
+            tried += 1;
+            Err::<(), _>(std::io::Error::other("unavailable"))
+        });
+        assert!(res.unwrap().is_err());
+        assert_eq!(tried, 2);
+    }
+}
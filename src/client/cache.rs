@@ -27,6 +27,11 @@ impl<T> Cache<T> {
     pub fn items(&self) -> &[T] {
         &self.items
     }
+
+    /// Gets the time a cache is valid until.
+    pub(crate) const fn valid_until(&self) -> Instant {
+        self.valid_until
+    }
 }
 
 impl<T> Default for Cache<T> {
@@ -0,0 +1,29 @@
+//! Current time as seen by policies, which tests can advance without sleeping.
+
+use std::time::Instant;
+#[cfg(test)]
+use std::{cell::Cell, time::Duration};
+
+#[cfg(test)]
+thread_local! {
+    /// How far tests have advanced the current thread's clock.
+    static ADVANCED: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+}
+
+/// Gets the current time.
+#[cfg(not(test))]
+pub fn now() -> Instant {
+    Instant::now()
+}
+
+/// Gets the current time, as advanced on the current thread by [`advance`].
+#[cfg(test)]
+pub fn now() -> Instant {
+    Instant::now() + ADVANCED.get()
+}
+
+/// Advances the current thread's clock by `duration`.
+#[cfg(test)]
+pub fn advance(duration: Duration) {
+    ADVANCED.set(ADVANCED.get() + duration);
+}
@@ -0,0 +1,129 @@
+//! Context passed to operations performed on SRV targets.
+
+use super::Target;
+use http::Uri;
+use std::time::Duration;
+
+/// Context of a single attempt of an operation on a SRV target, passed to
+/// operations performed by [`SrvClient::execute_with_context`] and
+/// [`SrvClient::execute_stream_with_context`].
+///
+/// [`SrvClient::execute_with_context`]: crate::SrvClient::execute_with_context
+/// [`SrvClient::execute_stream_with_context`]: crate::SrvClient::execute_stream_with_context
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AttemptContext {
+    pub(super) uri: Uri,
+    pub(super) target: Target,
+    pub(super) priority: Option<u16>,
+    pub(super) weight: Option<u16>,
+    pub(super) attempt: usize,
+    pub(super) attempt_in_round: usize,
+    pub(super) candidates: usize,
+    pub(super) round: usize,
+    pub(super) hedge: bool,
+    pub(super) timeout: Option<Duration>,
+    pub(super) remaining: Option<Duration>,
+}
+
+impl AttemptContext {
+    /// Gets the URI of the target the attempt is performed on.
+    #[must_use]
+    pub const fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// Converts into the URI of the target the attempt is performed on.
+    #[must_use]
+    pub fn into_uri(self) -> Uri {
+        self.uri
+    }
+
+    /// Gets the host of the target the attempt is performed on, like
+    /// [`Target::host`] (i.e. without brackets around IPv6 addresses).
+    #[must_use]
+    pub fn host(&self) -> &str {
+        self.target.host()
+    }
+
+    /// Gets the port of the target the attempt is performed on.
+    #[must_use]
+    pub const fn port(&self) -> u16 {
+        self.target.port()
+    }
+
+    /// Gets the host and port of the target the attempt is performed on.
+    #[must_use]
+    pub fn target(&self) -> Target {
+        self.target.clone()
+    }
+
+    /// Gets the SRV priority of the target, if known to the client's policy.
+    #[must_use]
+    pub const fn priority(&self) -> Option<u16> {
+        self.priority
+    }
+
+    /// Gets the SRV weight of the target, if known to the client's policy.
+    #[must_use]
+    pub const fn weight(&self) -> Option<u16> {
+        self.weight
+    }
+
+    /// Gets the index of the attempt among all attempts of the operation
+    /// (across every retry round), starting at zero.
+    #[must_use]
+    pub const fn attempt(&self) -> usize {
+        self.attempt
+    }
+
+    /// Gets the index of the attempt among the attempts of the current
+    /// round, starting at zero.
+    #[must_use]
+    pub const fn attempt_in_round(&self) -> usize {
+        self.attempt_in_round
+    }
+
+    /// Gets the number of SRV targets the operation may be attempted on in
+    /// the current round: the targets ordered by the client's policy, up to
+    /// the [maximum attempts](crate::SrvClient::max_attempts). The attempt
+    /// on the round's last fallback is the one for which
+    /// `attempt_in_round() + 1 == candidates()`.
+    #[must_use]
+    pub const fn candidates(&self) -> usize {
+        self.candidates
+    }
+
+    /// Gets the retry round the attempt is part of, starting at zero.
+    #[must_use]
+    pub const fn round(&self) -> usize {
+        self.round
+    }
+
+    /// Determines if the attempt is part of a retry round.
+    #[must_use]
+    pub const fn is_retry(&self) -> bool {
+        self.round > 0
+    }
+
+    /// Determines if the attempt was started as a hedge, i.e. while another
+    /// attempt was still in flight (see [`Execution::Hedged`]).
+    ///
+    /// [`Execution::Hedged`]: crate::Execution::Hedged
+    #[must_use]
+    pub const fn is_hedge(&self) -> bool {
+        self.hedge
+    }
+
+    /// Gets the time the attempt may take before it is abandoned, if limited
+    /// by the client's attempt timeout or the operation's deadline.
+    #[must_use]
+    pub const fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Gets the time remaining until the operation's deadline, if it has one.
+    #[must_use]
+    pub const fn remaining(&self) -> Option<Duration> {
+        self.remaining
+    }
+}
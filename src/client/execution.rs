@@ -0,0 +1,339 @@
+//! Execution modes for performing operations on SRV targets.
+
+use super::{AttemptError, IntoOutcome};
+use futures_timer::Delay;
+use futures_util::{
+    FutureExt,
+    future::{self, Either},
+    stream::{self, FuturesUnordered, Stream, StreamExt},
+};
+use std::{future::Future, pin::pin, time::Duration};
+
+/// Execution mode to use when performing an operation on SRV targets.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Execution {
+    /// Operations are performed *serially* (i.e. one after the other).
+    #[default]
+    Serial,
+    /// Operations are performed *concurrently* (i.e. all at once).
+    /// Note that this does not imply parallelism--no additional tasks are spawned.
+    Concurrent,
+    /// Operations are performed *concurrently*, with at most the given number
+    /// in flight at once. Further targets are started in the order determined
+    /// by the current policy as operations finish, and results are returned
+    /// in the order they become available. A limit of zero is treated as one.
+    ConcurrentLimit(usize),
+    /// Operations are *hedged*: an operation is started on the first target,
+    /// and another is started on the next target whenever `delay` passes
+    /// without a result (or as soon as an operation fails). Execution stops at
+    /// the first success (or terminal failure), abandoning any operations
+    /// still in flight.
+    Hedged {
+        /// Time to wait for a result before starting an operation on the next target.
+        delay: Duration,
+    },
+}
+
+/// Result of an operation on the target at some index (along with any other
+/// details of the attempt).
+///
+/// Keeps the result's types out of the generics of [`attempts`], so its
+/// returned stream lives as long as the operations producing it.
+pub trait Attempt {
+    /// Whether the operation succeeded.
+    fn succeeded(&self) -> bool;
+
+    /// Whether the operation failed such that no further targets should be tried.
+    fn terminal(&self) -> bool;
+}
+
+impl<I, T, E> Attempt for (I, Result<T, AttemptError<E>>) {
+    fn succeeded(&self) -> bool {
+        self.1.is_ok()
+    }
+
+    fn terminal(&self) -> bool {
+        matches!(self.1, Err(AttemptError::Terminal(_)))
+    }
+}
+
+/// Performs operations on the targets at the indices produced by `order`,
+/// producing a stream of their results according to `mode`. `func` is passed
+/// the index of a target and whether the operation is started as a hedge.
+pub fn attempts<O, F, Fut>(mode: Execution, order: O, func: F) -> impl Stream<Item = Fut::Output>
+where
+    O: Iterator<Item = usize>,
+    F: FnMut(usize, bool) -> Fut,
+    Fut: Future<Output: Attempt>,
+{
+    let mut func = func;
+    let attempts = match mode {
+        Execution::Serial => stream::iter(order)
+            .then(move |idx| func(idx, false))
+            .left_stream()
+            .left_stream(),
+        #[allow(clippy::from_iter_instead_of_collect)]
+        Execution::Concurrent => FuturesUnordered::from_iter(order.map(|idx| func(idx, false)))
+            .right_stream()
+            .left_stream(),
+        Execution::ConcurrentLimit(limit) => stream::iter(order)
+            .map(move |idx| func(idx, false))
+            .buffer_unordered(limit.max(1))
+            .left_stream()
+            .right_stream(),
+        Execution::Hedged { delay } => hedged(order, func, delay).right_stream().right_stream(),
+    };
+    // Stop (abandoning any operations in flight) after a terminal failure,
+    // without polling for (and so starting) any further attempts
+    stream::unfold(
+        (Box::pin(attempts), false),
+        |(mut attempts, stopped)| async move {
+            if stopped {
+                return None;
+            }
+            let attempt = attempts.next().await?;
+            let stopped = attempt.terminal();
+            Some((attempt, (attempts, stopped)))
+        },
+    )
+}
+
+/// Runs an attempt of an operation, abandoning it if it does not finish
+/// within `timeout`. Produces the attempt's result along with the class of
+/// its error, if the operation supplied one (see [`IntoOutcome::error_class`]).
+#[allow(clippy::type_complexity)]
+pub fn with_timeout<Fut>(
+    attempt: Fut,
+    timeout: Option<Duration>,
+) -> impl Future<
+    Output = (
+        Result<<Fut::Output as IntoOutcome>::Ok, AttemptError<<Fut::Output as IntoOutcome>::Error>>,
+        Option<&'static str>,
+    ),
+>
+where
+    Fut: Future<Output: IntoOutcome>,
+{
+    let timer = timeout.map(|timeout| (timeout, Delay::new(timeout)));
+    async move {
+        let attempt = attempt.map(|outcome| {
+            let error_class = outcome.error_class();
+            (outcome.into_outcome().into_attempt_result(), error_class)
+        });
+        match timer {
+            None => attempt.await,
+            Some((timeout, timer)) => match future::select(pin!(attempt), timer).await {
+                Either::Left((result, _)) => result,
+                Either::Right(_) => (Err(AttemptError::TimedOut(timeout)), None),
+            },
+        }
+    }
+}
+
+/// State of a hedged execution. See [`Execution::Hedged`].
+struct Hedge<O, F, Fut> {
+    order: O,
+    func: F,
+    in_flight: FuturesUnordered<Fut>,
+    /// Whether every target in `order` has been started.
+    exhausted: bool,
+    /// Whether an operation has succeeded or failed terminally.
+    done: bool,
+}
+
+impl<O, F, Fut> Hedge<O, F, Fut>
+where
+    O: Iterator<Item = usize>,
+    F: FnMut(usize, bool) -> Fut,
+{
+    /// Starts an operation on the next target, if there is one.
+    fn start_next(&mut self) {
+        match self.order.next() {
+            Some(idx) => {
+                let hedge = !self.in_flight.is_empty();
+                self.in_flight.push((self.func)(idx, hedge));
+            }
+            None => self.exhausted = true,
+        }
+    }
+}
+
+/// Performs operations as described in [`Execution::Hedged`].
+fn hedged<O, F, Fut>(order: O, func: F, delay: Duration) -> impl Stream<Item = Fut::Output>
+where
+    O: Iterator<Item = usize>,
+    F: FnMut(usize, bool) -> Fut,
+    Fut: Future<Output: Attempt>,
+{
+    let mut state = Hedge {
+        order,
+        func,
+        in_flight: FuturesUnordered::new(),
+        exhausted: false,
+        done: false,
+    };
+    state.start_next();
+    stream::unfold(state, move |mut state| async move {
+        loop {
+            if state.done {
+                return None;
+            }
+            // `None` if the delay passed before any in-flight operation finished
+            let finished = if state.exhausted {
+                Some(state.in_flight.next().await)
+            } else {
+                match future::select(state.in_flight.next(), Delay::new(delay)).await {
+                    Either::Left((finished, _)) => Some(finished),
+                    Either::Right(_) => None,
+                }
+            };
+            match finished {
+                None => state.start_next(),
+                // Nothing in flight and no targets left to try
+                Some(None) => return None,
+                Some(Some(attempt)) => {
+                    if attempt.succeeded() || attempt.terminal() {
+                        state.done = true;
+                    } else {
+                        state.start_next();
+                    }
+                    return Some((attempt, state));
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Outcome;
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::Instant,
+    };
+
+    /// Operation that fails on target `0`, hangs on target `1`, fails
+    /// terminally on target `3`, and succeeds on every other target after
+    /// `millis` milliseconds.
+    async fn outcome(idx: usize, millis: u64) -> Outcome<(), ()> {
+        match idx {
+            0 => Outcome::Failure(()),
+            1 => future::pending().await,
+            3 => Outcome::Terminal(()),
+            _ => {
+                Delay::new(Duration::from_millis(millis)).await;
+                Outcome::Success(())
+            }
+        }
+    }
+
+    fn operation(
+        idx: usize,
+        millis: u64,
+    ) -> impl Future<Output = (usize, Result<(), AttemptError<()>>)> {
+        with_timeout(outcome(idx, millis), None).map(move |(res, _)| (idx, res))
+    }
+
+    #[tokio::test]
+    async fn with_timeout_abandons_slow_attempt() {
+        let timeout = Some(Duration::from_millis(20));
+        let fast = with_timeout(outcome(2, 0), timeout).await;
+        assert_eq!(fast, (Ok(()), None));
+        let slow = with_timeout(outcome(1, 0), timeout).await;
+        assert!(matches!(slow, (Err(AttemptError::TimedOut(t)), None) if Some(t) == timeout));
+        let failed = with_timeout(outcome(0, 0), None).await;
+        assert_eq!(failed, (Err(AttemptError::Operation(())), None));
+    }
+
+    #[tokio::test]
+    async fn terminal_failure_stops_execution() {
+        let hedged = Execution::Hedged {
+            delay: Duration::from_secs(60),
+        };
+        for mode in [
+            Execution::Serial,
+            Execution::Concurrent,
+            Execution::ConcurrentLimit(1),
+            hedged,
+        ] {
+            let mut started = Vec::new();
+            let results = attempts(mode, [3, 2, 4].into_iter(), |idx, _| {
+                started.push(idx);
+                operation(idx, 10)
+            })
+            .collect::<Vec<_>>()
+            .await;
+            assert_eq!(results, vec![(3, Err(AttemptError::Terminal(())))]);
+            // Concurrent execution starts every target at once
+            let expected = match mode {
+                Execution::Concurrent => vec![3, 2, 4],
+                _ => vec![3],
+            };
+            assert_eq!(started, expected, "{mode:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_limit_bounds_in_flight() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let results = attempts(Execution::ConcurrentLimit(2), 4..9, |idx, _| {
+            let (in_flight, max_in_flight) = (Arc::clone(&in_flight), Arc::clone(&max_in_flight));
+            async move {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                max_in_flight.fetch_max(now, Ordering::SeqCst);
+                let res = operation(idx, 5).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                res
+            }
+        })
+        .collect::<Vec<_>>()
+        .await;
+        assert_eq!(results.len(), 5);
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn hedged_skips_hanging_target() {
+        let mode = Execution::Hedged {
+            delay: Duration::from_millis(20),
+        };
+        let start = Instant::now();
+        let mut started = Vec::new();
+        let results = attempts(mode, 0..4, |idx, hedge| {
+            started.push((idx, hedge));
+            operation(idx, 0)
+        })
+        .collect::<Vec<_>>()
+        .await;
+        // Target 0 fails and is replaced by target 1, which hangs until
+        // target 2 is started as a hedge after `delay` and succeeds, stopping execution
+        assert_eq!(
+            results,
+            vec![(0, Err(AttemptError::Operation(()))), (2, Ok(()))]
+        );
+        assert_eq!(started, vec![(0, false), (1, false), (2, true)]);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn hedged_waits_for_delay() {
+        let mode = Execution::Hedged {
+            delay: Duration::from_secs(60),
+        };
+        let mut started = Vec::new();
+        let results = attempts(mode, 2..5, |idx, hedge| {
+            started.push((idx, hedge));
+            operation(idx, 10)
+        })
+        .collect::<Vec<_>>()
+        .await;
+        // The first target succeeds before the delay passes, so no others are started
+        assert_eq!(results, vec![(2, Ok(()))]);
+        assert_eq!(started, vec![(2, false)]);
+    }
+}
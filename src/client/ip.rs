@@ -0,0 +1,120 @@
+//! IP addresses of SRV targets.
+
+use std::net::IpAddr;
+
+/// Address families to use when resolving SRV targets to IP addresses.
+///
+/// # Examples
+///
+/// ```
+/// use srv_rs::{AddressFamily, SrvClient};
+/// use srv_rs::resolver::manual::StaticResolver;
+///
+/// let resolver = StaticResolver::new_from_single_target("127.0.0.1", 5432);
+/// let client: SrvClient<_> =
+///     SrvClient::new_with_static_resolver(resolver).address_family(AddressFamily::V4Only);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum AddressFamily {
+    /// Both IPv4 and IPv6 addresses are used, alternating between families
+    /// starting with IPv6 as described in
+    /// [RFC 8305](https://tools.ietf.org/html/rfc8305#section-4).
+    #[default]
+    PreferV6,
+    /// Only IPv4 addresses are used.
+    V4Only,
+    /// Only IPv6 addresses are used.
+    V6Only,
+}
+
+impl AddressFamily {
+    /// Determines if IPv4 addresses are used.
+    #[must_use]
+    pub const fn includes_v4(self) -> bool {
+        !matches!(self, Self::V6Only)
+    }
+
+    /// Determines if IPv6 addresses are used.
+    #[must_use]
+    pub const fn includes_v6(self) -> bool {
+        !matches!(self, Self::V4Only)
+    }
+
+    /// Filters and orders a target's addresses according to the preference.
+    pub(crate) fn order(self, addrs: impl IntoIterator<Item = IpAddr>) -> Vec<IpAddr> {
+        let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(IpAddr::is_ipv6);
+        match self {
+            Self::V4Only => v4,
+            Self::V6Only => v6,
+            Self::PreferV6 => {
+                let mut ordered = Vec::with_capacity(v6.len() + v4.len());
+                let (mut v6, mut v4) = (v6.into_iter(), v4.into_iter());
+                loop {
+                    match (v6.next(), v4.next()) {
+                        (None, None) => return ordered,
+                        (v6, v4) => ordered.extend(v6.into_iter().chain(v4)),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Connects to the first of a target's addresses to accept a connection,
+/// racing connection attempts as described in
+/// [RFC 8305](https://tools.ietf.org/html/rfc8305#section-5).
+#[cfg(feature = "tokio")]
+pub async fn connect_tcp(addrs: &[std::net::SocketAddr]) -> std::io::Result<tokio::net::TcpStream> {
+    use super::{AttemptError, Execution, execution};
+    use futures_util::StreamExt;
+    use std::{io, pin::pin, time::Duration};
+
+    // Delay recommended by RFC 8305 before starting the next connection attempt
+    const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+    let mode = Execution::Hedged {
+        delay: CONNECTION_ATTEMPT_DELAY,
+    };
+    let attempts = execution::attempts(mode, 0..addrs.len(), |idx, _| async move {
+        let result = tokio::net::TcpStream::connect(addrs[idx]).await;
+        (idx, result.map_err(AttemptError::Operation))
+    });
+    let mut attempts = pin!(attempts);
+    let mut last_err = None;
+    while let Some((_, result)) = attempts.next().await {
+        match result {
+            Ok(stream) => return Ok(stream),
+            Err(AttemptError::Operation(err)) => last_err = Some(err),
+            Err(_) => {}
+        }
+    }
+    Err(last_err.unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no addresses")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addrs() -> Vec<IpAddr> {
+        ["10.0.0.1", "10.0.0.2", "10.0.0.3", "::1", "::2"]
+            .iter()
+            .map(|addr| addr.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn families_are_interleaved() {
+        let ordered = AddressFamily::PreferV6.order(addrs());
+        let ordered = ordered.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(ordered, ["::1", "10.0.0.1", "::2", "10.0.0.2", "10.0.0.3"]);
+    }
+
+    #[test]
+    fn families_are_filtered() {
+        let v4 = AddressFamily::V4Only.order(addrs());
+        let v6 = AddressFamily::V6Only.order(addrs());
+        assert!(v4.iter().all(IpAddr::is_ipv4));
+        assert!(v6.iter().all(IpAddr::is_ipv6));
+        assert_eq!((v4.len(), v6.len()), (3, 2));
+    }
+}
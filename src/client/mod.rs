@@ -2,22 +2,61 @@
 
 use crate::{
     SrvRecord,
-    resolver::{SrvResolver, manual::StaticResolver},
+    resolver::{IpResolver, SrvResolver, manual::StaticResolver},
 };
 use arc_swap::ArcSwap;
+use futures_timer::Delay;
 use futures_util::{
-    FutureExt, pin_mut,
-    stream::{self, Stream, StreamExt},
+    FutureExt,
+    future::{self, Either},
+    pin_mut,
+    stream::{Stream, StreamExt},
+};
+use http::uri::{PathAndQuery, Scheme, Uri};
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Display},
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    pin::pin,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
 };
-use http::uri::{Scheme, Uri};
-use std::{fmt::Debug, future::Future, sync::Arc, time::Instant};
 
 mod cache;
 pub use cache::Cache;
 
+mod clock;
+
+mod execution;
+pub use execution::Execution;
+
+mod context;
+pub use context::AttemptContext;
+
+mod options;
+pub use options::CallOptions;
+
+mod outcome;
+pub use outcome::{Classified, IntoOutcome, Outcome};
+
+mod retry;
+pub use retry::Retry;
+
+mod target;
+pub use target::Target;
+
+mod ip;
+pub use ip::AddressFamily;
+
+mod pool;
+pub use pool::{Manager, Pooled, SrvPool};
+
 /// SRV target selection policies.
 pub mod policy;
 
+pub mod blocking;
+
 /// Errors encountered by a [`SrvClient`].
 #[derive(Debug, thiserror::Error)]
 pub enum Error<Lookup: Debug> {
@@ -30,6 +69,130 @@ pub enum Error<Lookup: Debug> {
     /// Produced when there are no SRV targets for a client to use
     #[error("no SRV targets to use")]
     NoTargets,
+    /// Produced when an operation's deadline passes before any attempt of it succeeds
+    #[error("deadline exceeded after attempting {attempted} SRV targets")]
+    DeadlineExceeded {
+        /// Number of targets the operation was attempted on
+        attempted: usize,
+    },
+    /// Produced when there are fewer SRV targets than an operation requires
+    #[error("{required} SRV targets required but only {available} available")]
+    InsufficientTargets {
+        /// Number of targets the operation requires
+        required: usize,
+        /// Number of targets available
+        available: usize,
+    },
+}
+
+/// Errors produced by every failed attempt of an operation, each along with
+/// the SRV target it was attempted on.
+///
+/// Produced by [`SrvClient::execute_collecting_errors`].
+#[derive(Debug)]
+pub struct AttemptErrors<E> {
+    errors: Vec<(Uri, AttemptError<E>)>,
+}
+
+impl<E> AttemptErrors<E> {
+    /// Gets the errors along with their targets, in the order the attempts
+    /// finished (for serial execution, the order the targets were tried in).
+    #[must_use]
+    pub fn errors(&self) -> &[(Uri, AttemptError<E>)] {
+        &self.errors
+    }
+
+    /// Converts into the errors along with their targets.
+    #[must_use]
+    pub fn into_errors(self) -> Vec<(Uri, AttemptError<E>)> {
+        self.errors
+    }
+
+    /// Converts into the result [`SrvClient::execute`] produces, i.e. the last error.
+    fn into_last<T, L: Debug>(mut self) -> Result<Result<T, AttemptError<E>>, Error<L>> {
+        self.errors
+            .pop()
+            .map_or(Err(Error::NoTargets), |(_, err)| Ok(Err(err)))
+    }
+}
+
+impl<E: Display> Display for AttemptErrors<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "all {} attempts failed", self.errors.len())?;
+        for (idx, (candidate, err)) in self.errors.iter().enumerate() {
+            let sep = if idx == 0 { ": " } else { "; " };
+            write!(f, "{sep}{candidate}: {err}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<E: std::error::Error> std::error::Error for AttemptErrors<E> {}
+
+/// Errors produced when an operation cannot succeed on a quorum of SRV
+/// targets, along with the results of the attempts that did succeed.
+///
+/// Produced by [`SrvClient::execute_quorum`].
+#[derive(Debug)]
+pub struct QuorumErrors<T, E> {
+    successes: Vec<(Uri, T)>,
+    errors: AttemptErrors<E>,
+}
+
+impl<T, E> QuorumErrors<T, E> {
+    /// Gets the results of the successful attempts along with their targets,
+    /// in the order the attempts finished.
+    #[must_use]
+    pub fn successes(&self) -> &[(Uri, T)] {
+        &self.successes
+    }
+
+    /// Gets the errors of the failed attempts along with their targets, in the
+    /// order the attempts finished.
+    #[must_use]
+    pub fn errors(&self) -> &[(Uri, AttemptError<E>)] {
+        self.errors.errors()
+    }
+
+    /// Converts into the results of the successful attempts and the errors of
+    /// the failed ones.
+    #[must_use]
+    pub fn into_parts(self) -> (Vec<(Uri, T)>, AttemptErrors<E>) {
+        (self.successes, self.errors)
+    }
+}
+
+impl<T, E: Display> Display for QuorumErrors<T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "quorum not reached with {} successes, ",
+            self.successes.len()
+        )?;
+        write!(f, "{}", self.errors)
+    }
+}
+
+impl<T: Debug, E: std::error::Error> std::error::Error for QuorumErrors<T, E> {}
+
+/// Errors produced by a single attempt of an operation on a SRV target.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum AttemptError<E> {
+    /// Errors produced by the operation itself
+    #[error(transparent)]
+    Operation(E),
+    /// Errors produced by the operation that are not the fault of its target
+    /// (see [`Outcome::NotTargetFault`])
+    #[error(transparent)]
+    NotTargetFault(E),
+    /// Errors produced by the operation that stop further targets from being
+    /// tried (see [`Outcome::Terminal`])
+    #[error(transparent)]
+    Terminal(E),
+    /// Produced when an attempt does not finish within the client's
+    /// [attempt timeout](SrvClient::attempt_timeout)
+    #[error("attempt timed out after {0:?}")]
+    TimedOut(Duration),
 }
 
 /// Client for intelligently performing operations on a service located by SRV records.
@@ -61,17 +224,11 @@ pub struct SrvClient<Resolver, Policy: policy::Policy = policy::Affinity> {
     path_prefix: String,
     policy: Policy,
     cache: ArcSwap<Cache<Policy::CacheItem>>,
-}
-
-/// Execution mode to use when performing an operation on SRV targets.
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
-pub enum Execution {
-    /// Operations are performed *serially* (i.e. one after the other).
-    #[default]
-    Serial,
-    /// Operations are performed *concurrently* (i.e. all at once).
-    /// Note that this does not imply parallelism--no additional tasks are spawned.
-    Concurrent,
+    attempt_timeout: Option<Duration>,
+    retry: Retry,
+    max_attempts: Option<usize>,
+    address_family: AddressFamily,
+    ip_cache: Mutex<HashMap<String, Cache<IpAddr>>>,
 }
 
 impl<Resolver: Default, Policy: policy::Policy + Default> SrvClient<Resolver, Policy> {
@@ -106,6 +263,11 @@ impl<Resolver, Policy: policy::Policy + Default> SrvClient<Resolver, Policy> {
             path_prefix: String::from("/"),
             policy: Policy::default(),
             cache: ArcSwap::default(),
+            attempt_timeout: None,
+            retry: Retry::default(),
+            max_attempts: None,
+            address_family: AddressFamily::default(),
+            ip_cache: Mutex::default(),
         }
     }
 }
@@ -199,7 +361,18 @@ impl<Resolver: SrvResolver, Policy: policy::Policy> SrvClient<Resolver, Policy>
     /// determined by the current [`Policy`], and the results will be returned
     /// in the same order. If the concurrent execution mode is specified, the
     /// operation will be performed on all targets concurrently, and results
-    /// will be returned in the order they become available.
+    /// will be returned in the order they become available. If the bounded
+    /// concurrent execution mode is specified, the same holds, except that at
+    /// most the given number of operations will be in flight at once. If the
+    /// hedged execution mode is specified, the operation will be started on
+    /// targets in policy order as described in [`Execution::Hedged`], and the
+    /// stream will end after the first successful result.
+    ///
+    /// If the client has an [attempt timeout](SrvClient::attempt_timeout),
+    /// attempts that do not finish in time produce [`AttemptError::TimedOut`].
+    /// Operations may produce an [`Outcome`] rather than a [`Result`] to
+    /// classify their errors, e.g. to stop after a [terminal](Outcome::Terminal)
+    /// failure.
     ///
     /// # Examples
     ///
@@ -235,52 +408,76 @@ impl<Resolver: SrvResolver, Policy: policy::Policy> SrvClient<Resolver, Policy>
         &'a self,
         execution_mode: Execution,
         func: impl FnMut(Uri) -> Fut + 'a,
-    ) -> Result<impl Stream<Item = Result<T, E>> + 'a, Error<Resolver::Error>>
+    ) -> Result<impl Stream<Item = Result<T, AttemptError<E>>> + 'a, Error<Resolver::Error>>
     where
         E: std::error::Error,
-        Fut: Future<Output = Result<T, E>> + 'a,
+        Fut: Future<Output: IntoOutcome<Ok = T, Error = E>> + 'a,
     {
         let mut func = func;
+        let func = move |context: AttemptContext| func(context.into_uri());
+        self.execute_stream_with_context(execution_mode, func).await
+    }
+
+    /// Performs an operation on all of a client's SRV targets like
+    /// [`execute_stream`], but passes the operation an [`AttemptContext`]
+    /// describing each attempt rather than only its target's URI.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use futures::stream::StreamExt;
+    /// use srv_rs::{Execution, SrvClient};
+    /// use srv_rs::resolver::manual::StaticResolver;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let resolver = StaticResolver::new_from_single_target("server.example.com", 8080);
+    /// let client: SrvClient<_> = SrvClient::new_with_static_resolver(resolver);
+    /// let results = client
+    ///     .execute_stream_with_context(Execution::Serial, |context| async move {
+    ///         Ok::<_, std::io::Error>(format!("{}:{}", context.host(), context.port()))
+    ///     })
+    ///     .await
+    ///     .unwrap()
+    ///     .collect::<Vec<_>>()
+    ///     .await;
+    /// assert_eq!(results[0].as_ref().unwrap(), "server.example.com:8080");
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::Lookup`] if DNS resolution fails
+    /// - [`Error::RecordParsing`] if SRV records cannot be parsed into valid URIs
+    ///
+    /// [`execute_stream`]: SrvClient::execute_stream()
+    pub async fn execute_stream_with_context<'a, T, E, Fut>(
+        &'a self,
+        execution_mode: Execution,
+        func: impl FnMut(AttemptContext) -> Fut + 'a,
+    ) -> Result<impl Stream<Item = Result<T, AttemptError<E>>> + 'a, Error<Resolver::Error>>
+    where
+        E: std::error::Error,
+        Fut: Future<Output: IntoOutcome<Ok = T, Error = E>> + 'a,
+    {
+        self.check_path_prefix()?;
         let cache = self.get_valid_cache().await?;
-        let order = self.policy.order(cache.items());
-        let func = {
-            let cache = Arc::clone(&cache);
-            move |idx| {
-                let candidate = Policy::cache_item_to_uri(&cache.items()[idx]);
-                func(candidate.to_owned()).map(move |res| (idx, res))
-            }
-        };
-        let results = match execution_mode {
-            Execution::Serial => stream::iter(order).then(func).left_stream(),
-            #[allow(clippy::from_iter_instead_of_collect)]
-            Execution::Concurrent => {
-                stream::FuturesUnordered::from_iter(order.map(func)).right_stream()
-            }
+        let options = CallOptions {
+            max_attempts: self.max_attempts,
+            ..CallOptions::new()
         };
-        let results = results.map(move |(candidate_idx, result)| {
-            let candidate = Policy::cache_item_to_uri(&cache.items()[candidate_idx]);
-            match result {
-                Ok(res) => {
-                    #[cfg(feature = "log")]
-                    tracing::info!(URI = %candidate, "execution attempt succeeded");
-                    self.policy.note_success(candidate);
-                    Ok(res)
-                }
-                Err(err) => {
-                    #[cfg(feature = "log")]
-                    tracing::info!(URI = %candidate, error = %err, "execution attempt failed");
-                    self.policy.note_failure(candidate);
-                    Err(err)
-                }
-            }
-        });
-        Ok(results)
+        let order = self.order_targets(&cache, options);
+        let results = self.attempt_stream(cache, order, execution_mode, None, (0, 0), func);
+        Ok(results.map(|(_, result)| result))
     }
 
     /// Performs an operation on a client's SRV targets, producing the first
     /// successful result or the last error encountered if every execution of
     /// the operation was unsuccessful.
     ///
+    /// If every target fails, the operation is retried according to the
+    /// client's [retry configuration](SrvClient::retry).
+    ///
     /// # Examples
     ///
     /// ```
@@ -319,90 +516,1458 @@ impl<Resolver: SrvResolver, Policy: policy::Policy> SrvClient<Resolver, Policy>
         &self,
         execution_mode: Execution,
         func: impl FnMut(Uri) -> Fut,
-    ) -> Result<Result<T, E>, Error<Resolver::Error>>
+    ) -> Result<Result<T, AttemptError<E>>, Error<Resolver::Error>>
     where
         E: std::error::Error,
-        Fut: Future<Output = Result<T, E>>,
+        Fut: Future<Output: IntoOutcome<Ok = T, Error = E>>,
     {
-        let results = self.execute_stream(execution_mode, func).await?;
-        pin_mut!(results);
-
-        let mut last_error = None;
-        while let Some(result) = results.next().await {
-            match result {
-                Ok(res) => return Ok(Ok(res)),
-                Err(err) => last_error = Some(err),
-            }
-        }
-
-        last_error.map_or_else(|| Err(Error::NoTargets), |err| Ok(Err(err)))
+        self.check_path_prefix()?;
+        let options = CallOptions::new();
+        self.execute_projected(
+            execution_mode,
+            options,
+            AttemptContext::into_uri,
+            func,
+            |_| {},
+        )
+        .await
     }
 
-    fn parse_record(&self, record: &Resolver::Record) -> Result<Uri, http::Error> {
-        record.parse(self.http_scheme.clone(), self.path_prefix.as_str())
+    /// Performs an operation on a client's SRV targets like [`execute`], but
+    /// passes the operation the host and port of each target as a [`Target`]
+    /// rather than a URI, for services not addressed by URIs. The client's
+    /// [path prefix](SrvClient::path_prefix) is not applied to targets.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use srv_rs::{Execution, SrvClient};
+    /// use srv_rs::resolver::manual::StaticResolver;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let resolver = StaticResolver::new_from_single_target("db.example.com", 5432);
+    /// let client: SrvClient<_> = SrvClient::new_with_static_resolver(resolver);
+    /// let res = client
+    ///     .execute_target(Execution::Serial, |target| async move {
+    ///         // e.g. connect with `tokio::net::TcpStream::connect((target.host(), target.port()))`
+    ///         Ok::<_, std::io::Error>(target.to_string())
+    ///     })
+    ///     .await;
+    /// assert_eq!(res.unwrap().unwrap(), "db.example.com:5432");
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::Lookup`] if DNS resolution fails
+    /// - [`Error::RecordParsing`] if SRV records cannot be parsed into valid URIs
+    /// - [`Error::NoTargets`] if there are no SRV targets available
+    ///
+    /// [`execute`]: SrvClient::execute()
+    pub async fn execute_target<T, E, Fut>(
+        &self,
+        execution_mode: Execution,
+        func: impl FnMut(Target) -> Fut,
+    ) -> Result<Result<T, AttemptError<E>>, Error<Resolver::Error>>
+    where
+        E: std::error::Error,
+        Fut: Future<Output: IntoOutcome<Ok = T, Error = E>>,
+    {
+        let target = |context: AttemptContext| context.target();
+        self.execute_projected(execution_mode, CallOptions::new(), target, func, |_| {})
+            .await
     }
-}
 
-impl<Resolver, Policy: policy::Policy> SrvClient<Resolver, Policy> {
-    /// Sets the SRV name of the client.
-    #[must_use]
-    pub fn srv_name(self, srv_name: impl Into<String>) -> Self {
-        Self {
-            srv: srv_name.into(),
-            ..self
-        }
+    /// Performs an operation on a client's SRV targets like [`execute`], but
+    /// routes it by `key`, so that policies such as [`ConsistentHash`] try
+    /// targets in an order determined by the key (see [`CallOptions::key`]).
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::Lookup`] if DNS resolution fails
+    /// - [`Error::RecordParsing`] if SRV records cannot be parsed into valid URIs
+    /// - [`Error::NoTargets`] if there are no SRV targets available
+    ///
+    /// [`execute`]: SrvClient::execute()
+    /// [`ConsistentHash`]: policy::ConsistentHash
+    pub async fn execute_with_key<T, E, Fut>(
+        &self,
+        execution_mode: Execution,
+        key: impl AsRef<[u8]>,
+        func: impl FnMut(Uri) -> Fut,
+    ) -> Result<Result<T, AttemptError<E>>, Error<Resolver::Error>>
+    where
+        E: std::error::Error,
+        Fut: Future<Output: IntoOutcome<Ok = T, Error = E>>,
+    {
+        self.check_path_prefix()?;
+        let options = CallOptions::new().key(key);
+        self.execute_projected(
+            execution_mode,
+            options,
+            AttemptContext::into_uri,
+            func,
+            |_| {},
+        )
+        .await
     }
 
-    /// Sets the resolver of the client.
-    pub fn resolver<R>(self, resolver: R) -> SrvClient<R, Policy> {
-        SrvClient {
-            resolver,
-            cache: ArcSwap::default(),
-            policy: self.policy,
-            srv: self.srv,
-            http_scheme: self.http_scheme,
-            path_prefix: self.path_prefix,
-        }
+    /// Performs an operation on a client's SRV targets like [`execute`], but
+    /// produces the errors of every failed attempt, each along with the target
+    /// it was attempted on, if no attempt succeeds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use srv_rs::{Execution, SrvClient};
+    /// use srv_rs::resolver::manual::StaticResolver;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let resolver = StaticResolver::new_from_single_target("server.example.com", 8080);
+    /// let client: SrvClient<_> = SrvClient::new_with_static_resolver(resolver);
+    /// let res = client
+    ///     .execute_collecting_errors(Execution::Serial, |address| async move {
+    ///         address.to_string().parse::<usize>()
+    ///     })
+    ///     .await
+    ///     .unwrap();
+    /// let errors = res.unwrap_err();
+    /// assert_eq!(errors.errors()[0].0, "https://server.example.com:8080/");
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::Lookup`] if DNS resolution fails
+    /// - [`Error::RecordParsing`] if SRV records cannot be parsed into valid URIs
+    /// - [`Error::NoTargets`] if there are no SRV targets available
+    ///
+    /// [`execute`]: SrvClient::execute()
+    pub async fn execute_collecting_errors<T, E, Fut>(
+        &self,
+        execution_mode: Execution,
+        func: impl FnMut(Uri) -> Fut,
+    ) -> Result<Result<T, AttemptErrors<E>>, Error<Resolver::Error>>
+    where
+        E: std::error::Error,
+        Fut: Future<Output: IntoOutcome<Ok = T, Error = E>>,
+    {
+        let mut func = func;
+        let func = move |context: AttemptContext| func(context.into_uri());
+        self.execute_with_context(execution_mode, CallOptions::new(), func)
+            .await
     }
 
-    /// Sets the policy of the client.
+    /// Performs an operation on a client's SRV targets like [`execute`], but
+    /// within an overall `deadline` covering both the SRV lookup and every
+    /// attempt of the operation. No new targets are attempted once the
+    /// deadline has passed, and attempts still in flight at the deadline are
+    /// abandoned.
     ///
     /// # Examples
     ///
     /// ```
     /// # use srv_rs::EXAMPLE_SRV;
-    /// use hickory_resolver::Resolver;
-    /// use srv_rs::{policy::Rfc2782, SrvClient};
-    /// let client =
-    ///     SrvClient::<_>::new_with_resolver(EXAMPLE_SRV, Resolver::builder_tokio().unwrap().build())
-    ///         .policy(Rfc2782);
+    /// use hickory_resolver::{ResolveError, Resolver};
+    /// use srv_rs::{Error, Execution, SrvClient};
+    /// use std::time::{Duration, Instant};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error<ResolveError>> {
+    /// let resolver = Resolver::builder_tokio().unwrap().build();
+    /// let client: SrvClient<_> = SrvClient::new_with_resolver(EXAMPLE_SRV, resolver);
+    ///
+    /// let deadline = Instant::now() + Duration::from_secs(5);
+    /// let res = client
+    ///     .execute_with_deadline(deadline, Execution::Serial, |address| async move {
+    ///         Ok::<_, std::convert::Infallible>(address.to_string())
+    ///     })
+    ///     .await?;
+    /// assert!(res.is_ok());
+    /// # Ok(())
+    /// # }
     /// ```
-    pub fn policy<P: policy::Policy>(self, policy: P) -> SrvClient<Resolver, P> {
-        SrvClient {
-            policy,
-            cache: ArcSwap::default(),
-            resolver: self.resolver,
-            srv: self.srv,
-            http_scheme: self.http_scheme,
-            path_prefix: self.path_prefix,
-        }
-    }
-
-    /// Sets the http scheme of the client.
-    #[must_use]
-    pub fn http_scheme(self, http_scheme: Scheme) -> Self {
-        Self {
-            http_scheme,
-            ..self
-        }
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::Lookup`] if DNS resolution fails
+    /// - [`Error::RecordParsing`] if SRV records cannot be parsed into valid URIs
+    /// - [`Error::NoTargets`] if there are no SRV targets available
+    /// - [`Error::DeadlineExceeded`] if the deadline passes before any attempt succeeds
+    ///
+    /// [`execute`]: SrvClient::execute()
+    pub async fn execute_with_deadline<T, E, Fut>(
+        &self,
+        deadline: Instant,
+        execution_mode: Execution,
+        func: impl FnMut(Uri) -> Fut,
+    ) -> Result<Result<T, AttemptError<E>>, Error<Resolver::Error>>
+    where
+        E: std::error::Error,
+        Fut: Future<Output: IntoOutcome<Ok = T, Error = E>>,
+    {
+        self.check_path_prefix()?;
+        let options = CallOptions::new().deadline(deadline);
+        self.execute_projected(
+            execution_mode,
+            options,
+            AttemptContext::into_uri,
+            func,
+            |_| {},
+        )
+        .await
     }
 
-    /// Sets the path prefix of the client.
-    #[must_use]
-    pub fn path_prefix(self, path_prefix: impl Into<String>) -> Self {
-        Self {
-            path_prefix: path_prefix.into(),
-            ..self
-        }
+    /// Performs an operation on a client's SRV targets, passing the operation
+    /// an [`AttemptContext`] describing each attempt. This is the most general
+    /// form of [`execute`]: the client's configuration may be overridden by
+    /// `options` (e.g. to set a deadline like [`execute_with_deadline`]), and
+    /// the errors of every failed attempt are produced like
+    /// [`execute_collecting_errors`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use srv_rs::{CallOptions, Execution, SrvClient};
+    /// use srv_rs::resolver::manual::StaticResolver;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let resolver = StaticResolver::new_from_single_target("server.example.com", 8080);
+    /// let client: SrvClient<_> = SrvClient::new_with_static_resolver(resolver);
+    /// let options = CallOptions::new().deadline(Instant::now() + Duration::from_secs(5));
+    /// let res = client
+    ///     .execute_with_context(Execution::Serial, options, |context| async move {
+    ///         // e.g. set an `x-attempt` header and a timeout for the request
+    ///         assert_eq!(context.attempt(), 0);
+    ///         assert!(context.remaining().unwrap() <= Duration::from_secs(5));
+    ///         Ok::<_, std::io::Error>(context.into_uri())
+    ///     })
+    ///     .await;
+    /// assert!(matches!(res, Ok(Ok(_))));
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::Lookup`] if DNS resolution fails
+    /// - [`Error::RecordParsing`] if SRV records cannot be parsed into valid URIs
+    /// - [`Error::NoTargets`] if there are no SRV targets available
+    /// - [`Error::DeadlineExceeded`] if the deadline passes before any attempt succeeds
+    ///
+    /// [`execute`]: SrvClient::execute()
+    /// [`execute_with_deadline`]: SrvClient::execute_with_deadline()
+    /// [`execute_collecting_errors`]: SrvClient::execute_collecting_errors()
+    pub async fn execute_with_context<T, E, Fut>(
+        &self,
+        execution_mode: Execution,
+        options: CallOptions,
+        func: impl FnMut(AttemptContext) -> Fut,
+    ) -> Result<Result<T, AttemptErrors<E>>, Error<Resolver::Error>>
+    where
+        E: std::error::Error,
+        Fut: Future<Output: IntoOutcome<Ok = T, Error = E>>,
+    {
+        self.check_path_prefix()?;
+        self.execute_rounds(execution_mode, options, func, |_| {})
+            .await
+    }
+
+    /// Performs an operation like [`execute_rounds`], but passes it the part
+    /// of each attempt's context picked by `project` and produces the last
+    /// error encountered if every attempt fails.
+    ///
+    /// [`execute_rounds`]: SrvClient::execute_rounds()
+    async fn execute_projected<A, T, E, Fut>(
+        &self,
+        execution_mode: Execution,
+        options: CallOptions,
+        project: fn(AttemptContext) -> A,
+        mut func: impl FnMut(A) -> Fut,
+        on_cache: impl FnMut(&Arc<Cache<Policy::CacheItem>>),
+    ) -> Result<Result<T, AttemptError<E>>, Error<Resolver::Error>>
+    where
+        E: std::error::Error,
+        Fut: Future<Output: IntoOutcome<Ok = T, Error = E>>,
+    {
+        let func = |context| func(project(context));
+        let res = self
+            .execute_rounds(execution_mode, options, func, on_cache)
+            .await?;
+        res.map_or_else(AttemptErrors::into_last, |res| Ok(Ok(res)))
+    }
+
+    /// Performs an operation like [`execute_with_context`], passing
+    /// `on_cache` the cache of targets each round is performed on before any
+    /// of its attempts start.
+    ///
+    /// [`execute_with_context`]: SrvClient::execute_with_context()
+    async fn execute_rounds<T, E, Fut>(
+        &self,
+        execution_mode: Execution,
+        options: CallOptions,
+        func: impl FnMut(AttemptContext) -> Fut,
+        mut on_cache: impl FnMut(&Arc<Cache<Policy::CacheItem>>),
+    ) -> Result<Result<T, AttemptErrors<E>>, Error<Resolver::Error>>
+    where
+        E: std::error::Error,
+        Fut: Future<Output: IntoOutcome<Ok = T, Error = E>>,
+    {
+        let mut func = func;
+        let options = CallOptions {
+            max_attempts: options.max_attempts.or(self.max_attempts),
+            ..options
+        };
+        let deadline = options.deadline;
+        let mut errors = Vec::new();
+        let mut previous_cache = None;
+        for round in 0..self.retry.rounds() {
+            if round > 0 {
+                let backoff = self.retry.backoff_before(round - 1);
+                // Retrying is pointless if the deadline passes during the backoff
+                if deadline.is_some_and(|deadline| {
+                    Instant::now()
+                        .checked_add(backoff)
+                        .is_none_or(|end| end >= deadline)
+                }) {
+                    break;
+                }
+                #[cfg(feature = "log")]
+                tracing::info!(round, ?backoff, "every SRV target failed, retrying");
+                Delay::new(backoff).await;
+            }
+            let lookup = if round > 0 && self.retry.refreshes_cache() {
+                self.refresh_cache().left_future()
+            } else {
+                self.get_valid_cache().right_future()
+            };
+            let Some(cache) = before(deadline, lookup).await else {
+                break;
+            };
+            let cache = match (cache, previous_cache.take()) {
+                (Ok(cache), _) => cache,
+                // Retry the previous round's targets if they cannot be refreshed
+                #[cfg_attr(not(feature = "log"), allow(unused_variables))]
+                (Err(err), Some(previous)) => {
+                    #[cfg(feature = "log")]
+                    tracing::warn!(error = %err, "failed to refresh SRV targets, retrying previous targets");
+                    previous
+                }
+                (Err(err), None) => return Err(err),
+            };
+            on_cache(&cache);
+            previous_cache = Some(Arc::clone(&cache));
+            let first_attempt = (round, errors.len());
+            let order = self.order_targets(&cache, options);
+            let results = self.attempt_stream(
+                cache,
+                order,
+                execution_mode,
+                deadline,
+                first_attempt,
+                &mut func,
+            );
+            if let Some(res) = first_success(results, &mut errors).await {
+                return Ok(Ok(res));
+            }
+            if let Some((_, AttemptError::Terminal(_))) = errors.last() {
+                break;
+            }
+        }
+
+        // Every attempt started produces an error (attempts in flight time
+        // out at the deadline), so the errors account for every attempt
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            Err(Error::DeadlineExceeded {
+                attempted: errors.len(),
+            })
+        } else if errors.is_empty() {
+            Err(Error::NoTargets)
+        } else {
+            Ok(Err(AttemptErrors { errors }))
+        }
+    }
+
+    /// Performs an operation on every one of a client's SRV targets, producing
+    /// the result of each attempt along with its target. Unlike [`execute`],
+    /// this does not stop at the first success: every target is tried
+    /// regardless of the client's [maximum attempts](SrvClient::max_attempts)
+    /// unless an attempt fails [terminally](Outcome::Terminal) (or the hedged
+    /// execution mode, which stops at the first success, is specified).
+    /// Results are produced in the same order as by [`execute_stream`], with
+    /// any targets the client's policy leaves out (e.g. those ejected by
+    /// [`OutlierEjection`](policy::OutlierEjection)) tried last.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use srv_rs::{Execution, SrvClient};
+    /// use srv_rs::resolver::manual::{StaticResolver, StaticSrvRecord};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let records = (1..=3).map(|idx| StaticSrvRecord {
+    ///     target: format!("node{idx}.example.com"),
+    ///     port: 8080,
+    ///     priority: 0,
+    ///     weight: 0,
+    /// });
+    /// let client: SrvClient<_> = SrvClient::new_with_static_resolver(StaticResolver::new(records));
+    /// let results = client
+    ///     .execute_all(Execution::Concurrent, |address| async move {
+    ///         // e.g. ask the node at `address` to invalidate its cache
+    ///         Ok::<_, std::io::Error>(())
+    ///     })
+    ///     .await
+    ///     .unwrap();
+    /// assert_eq!(results.len(), 3);
+    /// for (address, result) in results {
+    ///     assert!(result.is_ok(), "{address} failed");
+    /// }
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::Lookup`] if DNS resolution fails
+    /// - [`Error::RecordParsing`] if SRV records cannot be parsed into valid URIs
+    ///
+    /// [`execute`]: SrvClient::execute()
+    /// [`execute_stream`]: SrvClient::execute_stream()
+    pub async fn execute_all<T, E, Fut>(
+        &self,
+        execution_mode: Execution,
+        func: impl FnMut(Uri) -> Fut,
+    ) -> Result<Vec<(Uri, Result<T, AttemptError<E>>)>, Error<Resolver::Error>>
+    where
+        E: std::error::Error,
+        Fut: Future<Output: IntoOutcome<Ok = T, Error = E>>,
+    {
+        let mut func = func;
+        self.check_path_prefix()?;
+        let cache = self.get_valid_cache().await?;
+        let func = |context: AttemptContext| func(context.into_uri());
+        let mut order = self.order_targets(&cache, CallOptions::new());
+        // Targets the policy leaves out are still part of the SRV set
+        let mut ordered = vec![false; cache.items().len()];
+        for &idx in &order {
+            ordered[idx] = true;
+        }
+        order.extend((0..ordered.len()).filter(|&idx| !ordered[idx]));
+        let results = self.attempt_stream(cache, order, execution_mode, None, (0, 0), func);
+        Ok(results.collect().await)
+    }
+
+    /// Performs an operation on all of a client's SRV targets concurrently,
+    /// producing the results of the first `quorum` successful attempts along
+    /// with their targets. Once `quorum` attempts have succeeded, attempts
+    /// still in flight are abandoned. If so many attempts fail that a quorum
+    /// can no longer be reached (or an attempt fails
+    /// [terminally](Outcome::Terminal)), the errors of the failed attempts are
+    /// produced immediately, along with the results of the attempts that
+    /// succeeded.
+    ///
+    /// Every target the client's policy orders is tried regardless of the
+    /// client's [maximum attempts](SrvClient::max_attempts), and operations are
+    /// not retried.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use srv_rs::SrvClient;
+    /// use srv_rs::resolver::manual::{StaticResolver, StaticSrvRecord};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let records = (1..=3).map(|idx| StaticSrvRecord {
+    ///     target: format!("replica{idx}.example.com"),
+    ///     port: 8080,
+    ///     priority: 0,
+    ///     weight: 0,
+    /// });
+    /// let client: SrvClient<_> = SrvClient::new_with_static_resolver(StaticResolver::new(records));
+    /// let written = client
+    ///     .execute_quorum(2, |address| async move {
+    ///         // e.g. write to the replica at `address`
+    ///         Ok::<_, std::io::Error>(())
+    ///     })
+    ///     .await
+    ///     .unwrap();
+    /// assert_eq!(written.unwrap().len(), 2);
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::Lookup`] if DNS resolution fails
+    /// - [`Error::RecordParsing`] if SRV records cannot be parsed into valid URIs
+    /// - [`Error::NoTargets`] if there are no SRV targets available
+    /// - [`Error::InsufficientTargets`] if the client's policy orders fewer than
+    ///   `quorum` SRV targets
+    pub async fn execute_quorum<T, E, Fut>(
+        &self,
+        quorum: usize,
+        func: impl FnMut(Uri) -> Fut,
+    ) -> Result<Result<Vec<(Uri, T)>, QuorumErrors<T, E>>, Error<Resolver::Error>>
+    where
+        E: std::error::Error,
+        Fut: Future<Output: IntoOutcome<Ok = T, Error = E>>,
+    {
+        let mut func = func;
+        self.check_path_prefix()?;
+        let cache = self.get_valid_cache().await?;
+        // Only targets the policy orders are attempted, so count towards a quorum
+        let order = self.order_targets(&cache, CallOptions::new());
+        let available = order.len();
+        if available == 0 {
+            return Err(Error::NoTargets);
+        } else if quorum > available {
+            return Err(Error::InsufficientTargets {
+                required: quorum,
+                available,
+            });
+        } else if quorum == 0 {
+            return Ok(Ok(Vec::new()));
+        }
+
+        let func = |context: AttemptContext| func(context.into_uri());
+        let results = self.attempt_stream(cache, order, Execution::Concurrent, None, (0, 0), func);
+        pin_mut!(results);
+        let (mut successes, mut errors) = (Vec::new(), Vec::new());
+        while let Some((candidate, result)) = results.next().await {
+            match result {
+                Ok(res) => successes.push((candidate, res)),
+                Err(err) => errors.push((candidate, err)),
+            }
+            if successes.len() == quorum {
+                return Ok(Ok(successes));
+            } else if available - errors.len() < quorum {
+                break;
+            }
+        }
+        // Either a quorum is unreachable or an attempt failed terminally
+        Ok(Err(QuorumErrors {
+            successes,
+            errors: AttemptErrors { errors },
+        }))
+    }
+
+    /// Orders the targets in `cache` by the client's policy, by `key` if set in
+    /// `options`, keeping at most `max_attempts` of them (if set in `options`).
+    fn order_targets(&self, cache: &Cache<Policy::CacheItem>, options: CallOptions) -> Vec<usize> {
+        let order = options.key.map_or_else(
+            || self.policy.order(cache.items()),
+            |key| self.policy.order_by_key(cache.items(), key),
+        );
+        order
+            .take(options.max_attempts.map_or(usize::MAX, |max| max.max(1)))
+            .collect()
+    }
+
+    /// Performs an operation on the targets in `cache` at the indices in
+    /// `order`, producing a stream of results as described in
+    /// [`execute_stream`]. If a `deadline` is set, no new targets are
+    /// attempted once it has passed, and attempts in flight time out at the
+    /// deadline. `first_attempt` is the retry round and index of the first
+    /// attempt in the stream.
+    ///
+    /// [`execute_stream`]: SrvClient::execute_stream()
+    fn attempt_stream<'a, T, E, Fut>(
+        &'a self,
+        cache: Arc<Cache<Policy::CacheItem>>,
+        order: Vec<usize>,
+        execution_mode: Execution,
+        deadline: Option<Instant>,
+        first_attempt: (usize, usize),
+        func: impl FnMut(AttemptContext) -> Fut + 'a,
+    ) -> impl Stream<Item = (Uri, Result<T, AttemptError<E>>)> + 'a
+    where
+        E: std::error::Error,
+        Fut: Future<Output: IntoOutcome<Ok = T, Error = E>> + 'a,
+    {
+        let mut func = func;
+        let candidates = order.len();
+        // No new targets are attempted once the deadline has passed
+        let order = order
+            .into_iter()
+            .take_while(move |_| deadline.is_none_or(|d| Instant::now() < d));
+        let attempt_timeout = self.attempt_timeout;
+        let policy = &self.policy;
+        let (round, first_attempt) = first_attempt;
+        let mut attempt = first_attempt;
+        let func = {
+            let cache = Arc::clone(&cache);
+            move |idx, hedge| {
+                let item = &cache.items()[idx];
+                let record = Policy::cache_item_to_record(item);
+                let remaining = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+                let timeout = match (attempt_timeout, remaining) {
+                    (Some(timeout), Some(remaining)) => Some(timeout.min(remaining)),
+                    (timeout, remaining) => timeout.or(remaining),
+                };
+                let uri = Policy::cache_item_to_uri(item);
+                let context = AttemptContext {
+                    uri: uri.to_owned(),
+                    target: record.map_or_else(|| Target::from_uri(uri), |r| r.target().clone()),
+                    priority: record.map(policy::ParsedRecord::priority),
+                    weight: record.map(policy::ParsedRecord::weight),
+                    attempt,
+                    attempt_in_round: attempt - first_attempt,
+                    candidates,
+                    round,
+                    hedge,
+                    timeout,
+                    remaining,
+                };
+                // Noted as cancelled if dropped before the attempt finishes
+                let in_flight = policy::InFlight::start(policy, context.uri.clone(), attempt);
+                let attempt_idx = attempt;
+                attempt += 1;
+                execution::with_timeout(func(context), timeout).map(move |(res, error_class)| {
+                    let elapsed = in_flight.end();
+                    ((idx, attempt_idx, elapsed, error_class), res)
+                })
+            }
+        };
+        let results = execution::attempts(execution_mode, order, func);
+        results.map(move |((candidate_idx, attempt, elapsed, error_class), result)| {
+            let candidate = Policy::cache_item_to_uri(&cache.items()[candidate_idx]);
+            match result {
+                Ok(res) => {
+                    #[cfg(feature = "log")]
+                    tracing::info!(URI = %candidate, ?elapsed, "execution attempt succeeded");
+                    let status = policy::AttemptStatus::Succeeded;
+                    let record = policy::AttemptRecord::new(status, elapsed, attempt, None);
+                    self.policy.note_success(candidate, &record);
+                    (candidate.clone(), Ok(res))
+                }
+                Err(err) => {
+                    #[cfg(feature = "log")]
+                    tracing::info!(URI = %candidate, ?elapsed, error = %err, "execution attempt failed");
+                    // Only failures that are the target's fault are noted
+                    let status = match err {
+                        AttemptError::Operation(_) => Some(policy::AttemptStatus::Failed),
+                        AttemptError::TimedOut(_) => Some(policy::AttemptStatus::TimedOut),
+                        _ => None,
+                    };
+                    if let Some(status) = status {
+                        let record =
+                            policy::AttemptRecord::new(status, elapsed, attempt, error_class);
+                        self.policy.note_failure(candidate, &record);
+                    }
+                    (candidate.clone(), Err(err))
+                }
+            }
+        })
+    }
+
+    fn parse_record(&self, record: &Resolver::Record) -> Result<Uri, http::Error> {
+        record.parse(self.http_scheme.clone(), self.path_prefix.as_str())
+    }
+
+    /// Checks that the client's path prefix is valid, which only operations
+    /// passed URIs rather than [`Target`]s require.
+    fn check_path_prefix(&self) -> Result<(), http::Error> {
+        PathAndQuery::try_from(self.path_prefix.as_str())?;
+        Ok(())
+    }
+}
+
+impl<Resolver: IpResolver, Policy: policy::Policy> SrvClient<Resolver, Policy> {
+    /// Resolves a SRV target to its socket addresses, using the client's
+    /// resolver and [address families](SrvClient::address_family). Addresses
+    /// are cached until their records expire.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use srv_rs::{SrvClient, Target};
+    /// use srv_rs::resolver::manual::StaticResolver;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let resolver = StaticResolver::new_from_single_target("127.0.0.1", 5432);
+    /// let client: SrvClient<_> = SrvClient::new_with_static_resolver(resolver);
+    /// let addrs = client.resolve_target(&Target::new("127.0.0.1", 5432)).await;
+    /// assert_eq!(addrs.unwrap(), ["127.0.0.1:5432".parse().unwrap()]);
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Lookup`] if DNS resolution fails.
+    pub async fn resolve_target(
+        &self,
+        target: &Target,
+    ) -> Result<Vec<SocketAddr>, Error<Resolver::Error>> {
+        let addrs = match target.socket_addr() {
+            Some(addr) => vec![addr.ip()],
+            None => self.get_ip_addrs(target.host()).await?,
+        };
+        let addrs = self.address_family.order(addrs).into_iter();
+        Ok(addrs
+            .map(|addr| SocketAddr::new(addr, target.port()))
+            .collect())
+    }
+
+    /// Gets the IP addresses of a host, from the client's cache if still valid.
+    async fn get_ip_addrs(&self, host: &str) -> Result<Vec<IpAddr>, Error<Resolver::Error>> {
+        let cached = self
+            .ip_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(host)
+            .filter(|cache| cache.valid())
+            .map(|cache| cache.items().to_vec());
+        if let Some(addrs) = cached {
+            return Ok(addrs);
+        }
+        let (addrs, valid_until) = self
+            .resolver
+            .get_ip_addrs(host, self.address_family)
+            .await
+            .map_err(Error::Lookup)?;
+        {
+            let mut ip_cache = self.ip_cache.lock().unwrap_or_else(PoisonError::into_inner);
+            ip_cache.retain(|_, cache| cache.valid());
+            ip_cache.insert(host.to_owned(), Cache::new(addrs.clone(), valid_until));
+        }
+        Ok(addrs)
+    }
+
+    /// Opens a TCP connection to the first of a client's SRV targets to
+    /// accept one, trying targets one at a time like [`execute`]. Each target
+    /// is [resolved](SrvClient::resolve_target) to its addresses, and
+    /// connection attempts to them are raced as described in
+    /// [RFC 8305](https://tools.ietf.org/html/rfc8305) ("Happy Eyeballs").
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use srv_rs::SrvClient;
+    /// use srv_rs::resolver::manual::StaticResolver;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// # let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    /// # let port = listener.local_addr().unwrap().port();
+    /// let resolver = StaticResolver::new_from_single_target("127.0.0.1", port);
+    /// let client: SrvClient<_> = SrvClient::new_with_static_resolver(resolver);
+    /// let stream = client.connect_tcp().await;
+    /// assert!(matches!(stream, Ok(Ok(_))));
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::Lookup`] if SRV resolution fails
+    /// - [`Error::RecordParsing`] if SRV records cannot be parsed into valid URIs
+    /// - [`Error::NoTargets`] if there are no SRV targets available
+    ///
+    /// [`execute`]: SrvClient::execute()
+    #[cfg(feature = "tokio")]
+    pub async fn connect_tcp(
+        &self,
+    ) -> Result<Result<tokio::net::TcpStream, AttemptError<std::io::Error>>, Error<Resolver::Error>>
+    where
+        Resolver::Error: Send + Sync,
+    {
+        let func = |target| async move {
+            let addrs = self
+                .resolve_target(&target)
+                .await
+                .map_err(std::io::Error::other)?;
+            ip::connect_tcp(&addrs).await
+        };
+        self.execute_target(Execution::Serial, func).await
+    }
+}
+
+/// Awaits `fut` if it completes before `deadline` (if any), producing `None` otherwise.
+async fn before<F: Future>(deadline: Option<Instant>, fut: F) -> Option<F::Output> {
+    let Some(deadline) = deadline else {
+        return Some(fut.await);
+    };
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    match future::select(pin!(fut), Delay::new(remaining)).await {
+        Either::Left((output, _)) => Some(output),
+        Either::Right(_) => None,
+    }
+}
+
+/// Produces the first successful result in `results`, collecting the errors
+/// produced before it (along with their targets) into `errors`.
+async fn first_success<T, E>(
+    results: impl Stream<Item = (Uri, Result<T, E>)>,
+    errors: &mut Vec<(Uri, E)>,
+) -> Option<T> {
+    pin_mut!(results);
+    while let Some((candidate, result)) = results.next().await {
+        match result {
+            Ok(res) => return Some(res),
+            Err(err) => errors.push((candidate, err)),
+        }
+    }
+    None
+}
+
+impl<Resolver, Policy: policy::Policy> SrvClient<Resolver, Policy> {
+    /// Sets the SRV name of the client.
+    #[must_use]
+    pub fn srv_name(self, srv_name: impl Into<String>) -> Self {
+        Self {
+            srv: srv_name.into(),
+            ..self
+        }
+    }
+
+    /// Sets the resolver of the client.
+    pub fn resolver<R>(self, resolver: R) -> SrvClient<R, Policy> {
+        SrvClient {
+            resolver,
+            cache: ArcSwap::default(),
+            ip_cache: Mutex::default(),
+            policy: self.policy,
+            srv: self.srv,
+            http_scheme: self.http_scheme,
+            path_prefix: self.path_prefix,
+            attempt_timeout: self.attempt_timeout,
+            retry: self.retry,
+            max_attempts: self.max_attempts,
+            address_family: self.address_family,
+        }
+    }
+
+    /// Sets the policy of the client.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use srv_rs::EXAMPLE_SRV;
+    /// use hickory_resolver::Resolver;
+    /// use srv_rs::{policy::Rfc2782, SrvClient};
+    /// let client =
+    ///     SrvClient::<_>::new_with_resolver(EXAMPLE_SRV, Resolver::builder_tokio().unwrap().build())
+    ///         .policy(Rfc2782);
+    /// ```
+    pub fn policy<P: policy::Policy>(self, policy: P) -> SrvClient<Resolver, P> {
+        SrvClient {
+            policy,
+            cache: ArcSwap::default(),
+            resolver: self.resolver,
+            srv: self.srv,
+            http_scheme: self.http_scheme,
+            path_prefix: self.path_prefix,
+            attempt_timeout: self.attempt_timeout,
+            retry: self.retry,
+            max_attempts: self.max_attempts,
+            address_family: self.address_family,
+            ip_cache: self.ip_cache,
+        }
+    }
+
+    /// Sets the http scheme of the client.
+    #[must_use]
+    pub fn http_scheme(self, http_scheme: Scheme) -> Self {
+        Self {
+            http_scheme,
+            ..self
+        }
+    }
+
+    /// Sets the path prefix of the client.
+    #[must_use]
+    pub fn path_prefix(self, path_prefix: impl Into<String>) -> Self {
+        Self {
+            path_prefix: path_prefix.into(),
+            ..self
+        }
+    }
+
+    /// Sets the maximum time a single attempt of an operation may take.
+    /// Attempts that take longer are abandoned, noted as failures to the
+    /// client's policy, and produce [`AttemptError::TimedOut`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use srv_rs::EXAMPLE_SRV;
+    /// use hickory_resolver::Resolver;
+    /// use srv_rs::SrvClient;
+    /// use std::time::Duration;
+    /// let client =
+    ///     SrvClient::<_>::new_with_resolver(EXAMPLE_SRV, Resolver::builder_tokio().unwrap().build())
+    ///         .attempt_timeout(Duration::from_secs(5));
+    /// ```
+    #[must_use]
+    pub fn attempt_timeout(self, attempt_timeout: Duration) -> Self {
+        Self {
+            attempt_timeout: Some(attempt_timeout),
+            ..self
+        }
+    }
+
+    /// Sets the retry configuration of the client, which determines how
+    /// operations behave when every SRV target fails. Retries apply to every
+    /// `execute_*` method that produces a single result ([`execute`],
+    /// [`execute_target`], [`execute_with_key`], [`execute_collecting_errors`],
+    /// [`execute_with_deadline`] and [`execute_with_context`]) and to
+    /// [`SrvPool::get`]; streams and fan-out methods perform a single round.
+    /// By default, operations are not retried.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use srv_rs::EXAMPLE_SRV;
+    /// use hickory_resolver::Resolver;
+    /// use srv_rs::{Retry, SrvClient};
+    /// let client =
+    ///     SrvClient::<_>::new_with_resolver(EXAMPLE_SRV, Resolver::builder_tokio().unwrap().build())
+    ///         .retry(Retry::new(3).refresh_cache(true));
+    /// ```
+    ///
+    /// [`execute`]: SrvClient::execute()
+    /// [`execute_target`]: SrvClient::execute_target()
+    /// [`execute_with_key`]: SrvClient::execute_with_key()
+    /// [`execute_collecting_errors`]: SrvClient::execute_collecting_errors()
+    /// [`execute_with_deadline`]: SrvClient::execute_with_deadline()
+    /// [`execute_with_context`]: SrvClient::execute_with_context()
+    #[must_use]
+    pub fn retry(self, retry: Retry) -> Self {
+        Self { retry, ..self }
+    }
+
+    /// Sets the maximum number of targets the client tries (in the order
+    /// determined by its policy) in each round of performing an operation.
+    /// A value of zero is treated as one. By default, every target is tried.
+    /// May be overridden for individual calls with [`CallOptions::max_attempts`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use srv_rs::EXAMPLE_SRV;
+    /// use hickory_resolver::Resolver;
+    /// use srv_rs::SrvClient;
+    /// let client =
+    ///     SrvClient::<_>::new_with_resolver(EXAMPLE_SRV, Resolver::builder_tokio().unwrap().build())
+    ///         .max_attempts(3);
+    /// ```
+    #[must_use]
+    pub fn max_attempts(self, max_attempts: usize) -> Self {
+        Self {
+            max_attempts: Some(max_attempts),
+            ..self
+        }
+    }
+
+    /// Sets the address families used when resolving SRV targets to IP
+    /// addresses (see [`SrvClient::resolve_target`]).
+    #[must_use]
+    pub fn address_family(self, address_family: AddressFamily) -> Self {
+        Self {
+            address_family,
+            // Cached addresses may be of families no longer used
+            ip_cache: Mutex::default(),
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::manual::StaticSrvRecord;
+    use std::{
+        cell::Cell,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    pub(super) fn client() -> SrvClient<StaticResolver, policy::Rfc2782> {
+        let records = StaticSrvRecord::numbered(3);
+        SrvClient::new_with_static_resolver(StaticResolver::new(records))
+    }
+
+    #[tokio::test]
+    async fn deadline_stops_serial_failover() {
+        let deadline = Instant::now() + Duration::from_millis(20);
+        let res = client()
+            .execute_with_deadline(deadline, Execution::Serial, |_| {
+                future::pending::<Result<(), std::io::Error>>()
+            })
+            .await;
+        assert!(matches!(res, Err(Error::DeadlineExceeded { attempted: 1 })));
+    }
+
+    #[tokio::test]
+    async fn deadline_returns_last_error_before_expiry() {
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let res = client()
+            .execute_with_deadline(deadline, Execution::Serial, |_| async {
+                Err::<(), _>(std::io::Error::other("unavailable"))
+            })
+            .await;
+        assert!(matches!(res, Ok(Err(AttemptError::Operation(_)))));
+    }
+
+    #[tokio::test]
+    async fn errors_are_collected_with_targets() {
+        let res = client()
+            .execute_collecting_errors(Execution::Serial, |candidate| async move {
+                Err::<(), _>(std::io::Error::other(candidate.host().unwrap().to_owned()))
+            })
+            .await;
+        let errors = res.unwrap().unwrap_err();
+        let targets = errors
+            .errors()
+            .iter()
+            .map(|(candidate, _)| candidate.to_string());
+        assert_eq!(
+            targets.collect::<Vec<_>>(),
+            (0..3)
+                .map(|priority| format!("https://server{priority}.example.com:8080/"))
+                .collect::<Vec<_>>()
+        );
+        assert!(errors.to_string().starts_with(
+            "all 3 attempts failed: \
+             https://server0.example.com:8080/: server0.example.com; "
+        ));
+    }
+
+    #[tokio::test]
+    async fn context_describes_attempts() {
+        let retry = Retry::new(2).backoff(Duration::ZERO, Duration::ZERO);
+        let mut contexts = Vec::new();
+        let res = client()
+            .retry(retry)
+            .execute_with_context(Execution::Serial, CallOptions::new(), |context| {
+                contexts.push(context);
+                future::ready(Err::<(), _>(std::io::Error::other("unavailable")))
+            })
+            .await;
+        assert_eq!(res.unwrap().unwrap_err().errors().len(), 6);
+        for (attempt, context) in contexts.iter().enumerate() {
+            assert_eq!(context.attempt(), attempt);
+            assert_eq!(context.round(), attempt / 3);
+            assert_eq!(context.priority(), u16::try_from(attempt % 3).ok());
+            assert_eq!(context.host(), format!("server{}.example.com", attempt % 3));
+            assert_eq!(context.port(), 8080);
+            assert_eq!(context.attempt_in_round(), attempt % 3);
+            assert_eq!(context.candidates(), 3);
+            assert!(!context.is_hedge());
+        }
+    }
+
+    #[tokio::test]
+    async fn context_candidates_follow_max_attempts() {
+        let mut last = Vec::new();
+        let res = client()
+            .execute_with_context(
+                Execution::Serial,
+                CallOptions::new().max_attempts(2),
+                |context| {
+                    last.push(context.attempt_in_round() + 1 == context.candidates());
+                    future::ready(Err::<(), _>(std::io::Error::other("unavailable")))
+                },
+            )
+            .await;
+        assert_eq!(res.unwrap().unwrap_err().errors().len(), 2);
+        // Only the second attempt is the round's last fallback
+        assert_eq!(last, [false, true]);
+    }
+
+    #[tokio::test]
+    async fn context_target_matches_record() {
+        let record = StaticSrvRecord {
+            target: String::from("[::1]"),
+            port: 5432,
+            priority: 0,
+            weight: 0,
+        };
+        let client: SrvClient<_, policy::Rfc2782> =
+            SrvClient::new_with_static_resolver(StaticResolver::new([record]));
+        let res = client
+            .execute_with_context(Execution::Serial, CallOptions::new(), |context| {
+                assert_eq!(context.host(), "::1");
+                assert_eq!(context.host(), context.target().host());
+                assert_eq!(context.port(), 5432);
+                future::ready(Ok::<_, std::io::Error>(context.into_uri()))
+            })
+            .await;
+        assert_eq!(res.unwrap().unwrap(), "https://[::1]:5432/");
+
+        // A path prefix that cannot be applied to the record's URI is only an
+        // error for operations passed URIs
+        let client = client.path_prefix("not a path");
+        let res = client
+            .execute_target(Execution::Serial, |target| {
+                future::ready(Ok::<_, std::io::Error>(target))
+            })
+            .await;
+        assert_eq!(res.unwrap().unwrap(), Target::new("::1", 5432));
+        let res = client
+            .execute(Execution::Serial, |uri| {
+                future::ready(Ok::<_, std::io::Error>(uri))
+            })
+            .await;
+        assert!(matches!(res, Err(Error::RecordParsing(_))));
+        let res = client
+            .execute_with_context(Execution::Serial, CallOptions::new(), |context| {
+                future::ready(Ok::<_, std::io::Error>(context.into_uri()))
+            })
+            .await;
+        assert!(matches!(res, Err(Error::RecordParsing(_))));
+    }
+
+    #[tokio::test]
+    async fn max_attempts_limits_targets_tried() {
+        let client = client().max_attempts(2);
+        for (mode, options, expected) in [
+            (Execution::Serial, CallOptions::new(), 2),
+            (Execution::Concurrent, CallOptions::new(), 2),
+            (Execution::Concurrent, CallOptions::new().max_attempts(1), 1),
+            (Execution::Serial, CallOptions::new().max_attempts(10), 3),
+        ] {
+            let res = client
+                .execute_with_context(mode, options, |_| async {
+                    Err::<(), _>(std::io::Error::other("unavailable"))
+                })
+                .await;
+            assert_eq!(res.unwrap().unwrap_err().errors().len(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn all_targets_are_tried() {
+        let client = client().max_attempts(1);
+        let results = client
+            .execute_all(Execution::Serial, |candidate| async move {
+                match candidate.host() {
+                    Some("server1.example.com") => Err(std::io::Error::other("unavailable")),
+                    _ => Ok(()),
+                }
+            })
+            .await
+            .unwrap();
+        let results = results
+            .iter()
+            .map(|(candidate, result)| (candidate.host().unwrap(), result.is_ok()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            results,
+            [
+                ("server0.example.com", true),
+                ("server1.example.com", false),
+                ("server2.example.com", true)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn all_targets_are_tried_even_if_left_out_by_policy() {
+        let policy = policy::OutlierEjection::new().consecutive_failures(1);
+        let client = client().policy(policy);
+        let fail_first = |candidate: Uri| async move {
+            match candidate.host() {
+                Some("server0.example.com") => Err(std::io::Error::other("unavailable")),
+                _ => Ok(()),
+            }
+        };
+        // Target 0 fails once, so is ejected and left out of the order
+        let res = client.execute(Execution::Serial, fail_first).await;
+        assert!(matches!(res, Ok(Ok(()))));
+        let results = client
+            .execute_all(Execution::Serial, fail_first)
+            .await
+            .unwrap();
+        let results = results
+            .iter()
+            .map(|(candidate, _)| candidate.host().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            results,
+            [
+                "server1.example.com",
+                "server2.example.com",
+                "server0.example.com"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn quorum_fails_early_once_unreachable() {
+        let client = client();
+        // Targets 0 and 1 fail immediately while target 2 hangs
+        let res = client
+            .execute_quorum(2, |candidate| async move {
+                if candidate.host() == Some("server2.example.com") {
+                    future::pending().await
+                } else {
+                    Err::<(), _>(std::io::Error::other("unavailable"))
+                }
+            })
+            .await;
+        let errors = res.unwrap().unwrap_err();
+        assert_eq!(errors.errors().len(), 2);
+        assert!(errors.successes().is_empty());
+
+        let res = client
+            .execute_quorum(2, |candidate| async move {
+                match candidate.host() {
+                    Some("server0.example.com") => Err(std::io::Error::other("unavailable")),
+                    _ => Ok(candidate.port_u16()),
+                }
+            })
+            .await;
+        assert_eq!(res.unwrap().unwrap().len(), 2);
+
+        let res = client
+            .execute_quorum(4, |_| async { Ok::<_, std::io::Error>(()) })
+            .await;
+        assert!(matches!(
+            res,
+            Err(Error::InsufficientTargets {
+                required: 4,
+                available: 3
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn quorum_counts_targets_the_policy_orders() {
+        let policy = policy::OutlierEjection::new().consecutive_failures(1);
+        let client = client().policy(policy);
+        // Target 0 fails once, so is ejected and left out of the order
+        let res = client
+            .execute(Execution::Serial, |candidate| async move {
+                match candidate.host() {
+                    Some("server0.example.com") => Err(std::io::Error::other("unavailable")),
+                    _ => Ok(()),
+                }
+            })
+            .await;
+        assert!(matches!(res, Ok(Ok(()))));
+        let res = client
+            .execute_quorum(3, |_| async { Ok::<_, std::io::Error>(()) })
+            .await;
+        assert!(matches!(
+            res,
+            Err(Error::InsufficientTargets {
+                required: 3,
+                available: 2
+            })
+        ));
+
+        // Successes are produced along with the errors if a quorum is unreachable
+        let res = client
+            .execute_quorum(2, |candidate| async move {
+                match candidate.host() {
+                    Some("server1.example.com") => Ok(()),
+                    _ => Err(std::io::Error::other("unavailable")),
+                }
+            })
+            .await;
+        let (successes, errors) = res.unwrap().unwrap_err().into_parts();
+        assert_eq!(successes.len(), 1);
+        assert_eq!(successes[0].0.host(), Some("server1.example.com"));
+        assert_eq!(errors.errors().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn retry_performs_further_rounds() {
+        let retry = Retry::new(2).backoff(Duration::ZERO, Duration::ZERO);
+        let attempts = Cell::new(0);
+        let res = client()
+            .retry(retry)
+            .execute(Execution::Serial, |_| {
+                attempts.set(attempts.get() + 1);
+                let res = match attempts.get() {
+                    4 => Ok(()),
+                    _ => Err(std::io::Error::other("unavailable")),
+                };
+                future::ready(res)
+            })
+            .await;
+        assert!(matches!(res, Ok(Ok(()))));
+        assert_eq!(attempts.get(), 4);
+    }
+
+    /// Resolver whose lookups fail after the first.
+    #[derive(Default)]
+    struct FlakyResolver(AtomicUsize);
+
+    #[async_trait::async_trait]
+    impl SrvResolver for FlakyResolver {
+        type Record = StaticSrvRecord;
+        type Error = std::io::Error;
+
+        async fn get_srv_records_unordered(
+            &self,
+            _srv: &str,
+        ) -> Result<(Vec<StaticSrvRecord>, Instant), Self::Error> {
+            if self.0.fetch_add(1, Ordering::Relaxed) > 0 {
+                return Err(std::io::Error::other("lookup failed"));
+            }
+            let record = StaticSrvRecord {
+                target: String::from("server.example.com"),
+                port: 8080,
+                priority: 0,
+                weight: 0,
+            };
+            Ok((vec![record], Instant::now() + Duration::from_secs(60)))
+        }
+    }
+
+    #[tokio::test]
+    async fn retry_keeps_targets_if_refresh_fails() {
+        let retry = Retry::new(3)
+            .backoff(Duration::ZERO, Duration::ZERO)
+            .refresh_cache(true);
+        let client = SrvClient::<_>::new_with_resolver("", FlakyResolver::default()).retry(retry);
+        let res = client
+            .execute_collecting_errors(Execution::Serial, |_| {
+                future::ready(Err::<(), _>(std::io::Error::other("unavailable")))
+            })
+            .await;
+        let errors = res.unwrap().unwrap_err();
+        assert_eq!(errors.errors().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn retry_stops_if_backoff_outlasts_deadline() {
+        // Backoffs are jittered, so are bounded far beyond the deadline
+        let bound = Duration::MAX;
+        let retry = Retry::new(2).backoff(bound, bound);
+        let deadline = Instant::now() + Duration::from_secs(1);
+        let options = CallOptions::new().deadline(deadline);
+        let res = client()
+            .retry(retry)
+            .execute_with_context(Execution::Serial, options, |_| {
+                future::ready(Err::<(), _>(std::io::Error::other("unavailable")))
+            })
+            .await;
+        assert_eq!(res.unwrap().unwrap_err().errors().len(), 3);
+        assert!(Instant::now() < deadline);
+    }
+
+    /// Resolver counting lookups of IP addresses, which are valid for `ttl`.
+    struct CountingResolver {
+        lookups: AtomicUsize,
+        ttl: Duration,
+    }
+
+    #[async_trait::async_trait]
+    impl SrvResolver for CountingResolver {
+        type Record = StaticSrvRecord;
+        type Error = std::io::Error;
+
+        async fn get_srv_records_unordered(
+            &self,
+            _srv: &str,
+        ) -> Result<(Vec<StaticSrvRecord>, Instant), Self::Error> {
+            Ok((Vec::new(), Instant::now()))
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl IpResolver for CountingResolver {
+        async fn get_ip_addrs(
+            &self,
+            _host: &str,
+            family: AddressFamily,
+        ) -> Result<(Vec<IpAddr>, Instant), Self::Error> {
+            self.lookups.fetch_add(1, Ordering::Relaxed);
+            let addrs = ["10.0.0.1", "::1"].map(|addr| addr.parse::<IpAddr>().unwrap());
+            let addrs = addrs
+                .into_iter()
+                .filter(|addr| match addr {
+                    IpAddr::V4(_) => family.includes_v4(),
+                    IpAddr::V6(_) => family.includes_v6(),
+                })
+                .collect();
+            Ok((addrs, Instant::now() + self.ttl))
+        }
+    }
+
+    #[tokio::test]
+    async fn resolved_addresses_are_cached_until_expiry() {
+        let target = Target::new("server.example.com", 80);
+        for (ttl, lookups) in [(Duration::from_secs(60), 1), (Duration::ZERO, 2)] {
+            let resolver = CountingResolver {
+                lookups: AtomicUsize::new(0),
+                ttl,
+            };
+            let client = SrvClient::<_>::new_with_resolver("", resolver);
+            for _ in 0..2 {
+                let addrs = client.resolve_target(&target).await.unwrap();
+                assert_eq!(addrs.len(), 2);
+            }
+            assert_eq!(client.resolver.lookups.load(Ordering::Relaxed), lookups);
+            // Addresses cached for both families are not reused for one
+            let client = client.address_family(AddressFamily::V4Only);
+            let addrs = client.resolve_target(&target).await.unwrap();
+            assert_eq!(addrs, ["10.0.0.1:80".parse().unwrap()]);
+            assert_eq!(client.resolver.lookups.load(Ordering::Relaxed), lookups + 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn targets_resolve_to_preferred_families() {
+        let client = client().address_family(AddressFamily::V4Only);
+        let addrs = client.resolve_target(&Target::new("::1", 80)).await;
+        assert_eq!(addrs.unwrap(), []);
+        let addrs = client.resolve_target(&Target::new("127.0.0.1", 80)).await;
+        assert_eq!(addrs.unwrap(), ["127.0.0.1:80".parse().unwrap()]);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn connect_tcp_fails_over() {
+        // Nothing listens on the first target's port once its listener is dropped
+        let closed = {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open = listener.local_addr().unwrap();
+        let records = [closed, open.port()]
+            .into_iter()
+            .zip(0..)
+            .map(|(port, priority)| StaticSrvRecord {
+                target: String::from("127.0.0.1"),
+                port,
+                priority,
+                weight: 0,
+            });
+        let client: SrvClient<_, policy::Rfc2782> =
+            SrvClient::new_with_static_resolver(StaticResolver::new(records));
+        let stream = client.connect_tcp().await.unwrap().unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), open);
+    }
+
+    #[tokio::test]
+    async fn abandoned_attempts_end() {
+        let records = StaticSrvRecord::numbered(2);
+        let client: SrvClient<_, policy::LeastLoaded> =
+            SrvClient::new_with_static_resolver(StaticResolver::new(records));
+        let mode = Execution::Hedged {
+            delay: Duration::from_millis(10),
+        };
+        let res = client
+            .execute(mode, |candidate| async move {
+                if candidate.host() == Some("server0.example.com") {
+                    future::pending::<()>().await;
+                }
+                Ok::<_, std::io::Error>(candidate)
+            })
+            .await;
+        let succeeded = res.unwrap().unwrap();
+        assert_eq!(succeeded.host(), Some("server1.example.com"));
+        // The hanging attempt on server0 ended when it was abandoned
+        let hanging = "https://server0.example.com:8080/".parse().unwrap();
+        assert_eq!(client.policy.in_flight(&hanging), 0);
+        assert_eq!(client.policy.in_flight(&succeeded), 0);
     }
 }
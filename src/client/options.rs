@@ -0,0 +1,69 @@
+//! Options for individual calls performing operations on SRV targets.
+
+use std::time::Instant;
+
+/// Options for a single call performing an operation on a client's SRV
+/// targets, overriding the client's configuration where set.
+///
+/// # Examples
+///
+/// ```
+/// use srv_rs::CallOptions;
+/// use std::time::{Duration, Instant};
+/// let options = CallOptions::new()
+///     .deadline(Instant::now() + Duration::from_secs(5))
+///     .max_attempts(2);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CallOptions {
+    pub(super) deadline: Option<Instant>,
+    pub(super) max_attempts: Option<usize>,
+    pub(super) key: Option<u64>,
+}
+
+impl CallOptions {
+    /// Creates options that do not override any of a client's configuration.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            deadline: None,
+            max_attempts: None,
+            key: None,
+        }
+    }
+
+    /// Sets an overall deadline covering both the SRV lookup and every
+    /// attempt of the operation. No new targets are attempted once the
+    /// deadline has passed, and attempts still in flight at the deadline are
+    /// abandoned.
+    #[must_use]
+    pub const fn deadline(self, deadline: Instant) -> Self {
+        Self {
+            deadline: Some(deadline),
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of targets to try in each round, overriding
+    /// the client's [maximum](crate::SrvClient::max_attempts). A value of zero
+    /// is treated as one.
+    #[must_use]
+    pub const fn max_attempts(self, max_attempts: usize) -> Self {
+        Self {
+            max_attempts: Some(max_attempts),
+            ..self
+        }
+    }
+
+    /// Sets a key to route the call by, so that policies such as
+    /// [`ConsistentHash`] try targets in an order determined by the key.
+    ///
+    /// [`ConsistentHash`]: crate::policy::ConsistentHash
+    #[must_use]
+    pub fn key(self, key: impl AsRef<[u8]>) -> Self {
+        Self {
+            key: Some(super::policy::hash(0, key.as_ref())),
+            ..self
+        }
+    }
+}
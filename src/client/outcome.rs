@@ -0,0 +1,142 @@
+//! Classified outcomes of operations performed on SRV targets.
+
+use super::AttemptError;
+
+/// Outcome of an operation performed on a SRV target, classifying errors by
+/// how a [`SrvClient`] should react to them.
+///
+/// Operations may produce an [`Outcome`] instead of a [`Result`] to control
+/// failover and policy feedback. Producing a [`Result`] is equivalent to
+/// producing [`Outcome::Success`] or [`Outcome::Failure`].
+///
+/// # Examples
+///
+/// ```
+/// use srv_rs::{Execution, Outcome, SrvClient};
+/// use srv_rs::resolver::manual::StaticResolver;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let resolver = StaticResolver::new_from_single_target("server.example.com", 8080);
+/// let client: SrvClient<_> = SrvClient::new_with_static_resolver(resolver);
+/// let res = client
+///     .execute(Execution::Serial, |_| async move {
+///         // e.g. a request rejected as malformed would be rejected by any target
+///         Outcome::<(), _>::Terminal(std::io::Error::other("bad request"))
+///     })
+///     .await;
+/// # }
+/// ```
+///
+/// [`SrvClient`]: crate::SrvClient
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Outcome<T, E> {
+    /// The operation succeeded.
+    Success(T),
+    /// The operation failed because of its target. The failure is noted by
+    /// the client's policy and the next target is tried.
+    Failure(E),
+    /// The operation failed through no fault of its target. The failure is
+    /// not noted by the client's policy, and the next target is tried.
+    NotTargetFault(E),
+    /// The operation failed in a way no other target would remedy. The
+    /// failure is not noted by the client's policy, and no further targets
+    /// are tried.
+    Terminal(E),
+}
+
+impl<T, E> Outcome<T, E> {
+    /// Converts an outcome into the result of an attempt.
+    pub(crate) fn into_attempt_result(self) -> Result<T, AttemptError<E>> {
+        match self {
+            Self::Success(res) => Ok(res),
+            Self::Failure(err) => Err(AttemptError::Operation(err)),
+            Self::NotTargetFault(err) => Err(AttemptError::NotTargetFault(err)),
+            Self::Terminal(err) => Err(AttemptError::Terminal(err)),
+        }
+    }
+}
+
+impl<T, E> From<Result<T, E>> for Outcome<T, E> {
+    fn from(result: Result<T, E>) -> Self {
+        match result {
+            Ok(res) => Self::Success(res),
+            Err(err) => Self::Failure(err),
+        }
+    }
+}
+
+/// Conversion into an [`Outcome`], implemented for the values operations
+/// performed by a [`SrvClient`] may produce.
+///
+/// [`SrvClient`]: crate::SrvClient
+pub trait IntoOutcome {
+    /// Type of successful results.
+    type Ok;
+    /// Type of errors.
+    type Error;
+
+    /// Converts a value into an [`Outcome`].
+    fn into_outcome(self) -> Outcome<Self::Ok, Self::Error>;
+
+    /// Gets a class describing the error of a failed operation, which is
+    /// passed to the client's policy (see [`AttemptRecord::error_class`]).
+    ///
+    /// [`AttemptRecord::error_class`]: crate::policy::AttemptRecord::error_class
+    fn error_class(&self) -> Option<&'static str> {
+        None
+    }
+}
+
+/// Outcome of an operation along with a class describing its error, which is
+/// passed to the client's policy (see [`AttemptRecord::error_class`]).
+///
+/// # Examples
+///
+/// ```
+/// use srv_rs::{Classified, Outcome};
+/// let outcome = Classified {
+///     outcome: Outcome::<(), _>::Failure(std::io::Error::other("too many requests")),
+///     class: "overloaded",
+/// };
+/// ```
+///
+/// [`AttemptRecord::error_class`]: crate::policy::AttemptRecord::error_class
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Classified<O> {
+    /// Outcome of the operation.
+    pub outcome: O,
+    /// Class describing the operation's error.
+    pub class: &'static str,
+}
+
+impl<O: IntoOutcome> IntoOutcome for Classified<O> {
+    type Ok = O::Ok;
+    type Error = O::Error;
+
+    fn into_outcome(self) -> Outcome<Self::Ok, Self::Error> {
+        self.outcome.into_outcome()
+    }
+
+    fn error_class(&self) -> Option<&'static str> {
+        Some(self.class)
+    }
+}
+
+impl<T, E> IntoOutcome for Outcome<T, E> {
+    type Ok = T;
+    type Error = E;
+
+    fn into_outcome(self) -> Self {
+        self
+    }
+}
+
+impl<T, E> IntoOutcome for Result<T, E> {
+    type Ok = T;
+    type Error = E;
+
+    fn into_outcome(self) -> Outcome<T, E> {
+        self.into()
+    }
+}
@@ -1,10 +1,17 @@
-use crate::{Error, SrvClient, SrvRecord, resolver::SrvResolver};
+use crate::{Error, SrvClient, SrvRecord, Target, resolver::SrvResolver};
 use arc_swap::ArcSwapOption;
 use async_trait::async_trait;
 use http::Uri;
-use std::sync::Arc;
+use rand::Rng;
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
 
 pub use super::Cache;
+use super::clock;
 
 /// Policy for [`SrvClient`] to use when selecting SRV targets to recommend.
 #[async_trait]
@@ -15,102 +22,422 @@ pub trait Policy: Sized + Send + Sync {
     /// Iterator of indices used to order cache items.
     type Ordering: Iterator<Item = usize>;
 
-    /// Obtains a refreshed cache for a client.
-    async fn refresh_cache<Resolver: SrvResolver>(
+    /// Obtains a refreshed cache for a client, which may use a different
+    /// policy that wraps this one (e.g. [`Affinity`]).
+    async fn refresh_cache<Resolver: SrvResolver, P: Policy>(
         &self,
-        client: &SrvClient<Resolver, Self>,
+        client: &SrvClient<Resolver, P>,
     ) -> Result<Cache<Self::CacheItem>, Error<Resolver::Error>>;
 
     /// Creates an iterator of indices corresponding to cache items in the
     /// order a [`SrvClient`] should try using them to perform an operation.
     fn order(&self, items: &[Self::CacheItem]) -> Self::Ordering;
 
+    /// Creates an iterator of indices like [`Policy::order`], but for an
+    /// operation routed by the hash of a key (see [`CallOptions::key`]).
+    /// Policies that do not route by key order items as usual.
+    ///
+    /// [`CallOptions::key`]: crate::CallOptions::key
+    #[allow(unused_variables)]
+    fn order_by_key(&self, items: &[Self::CacheItem], key: u64) -> Self::Ordering {
+        self.order(items)
+    }
+
     /// Converts a reference to a cached item into a reference to a [`Uri`].
     fn cache_item_to_uri(item: &Self::CacheItem) -> &Uri;
 
-    /// Makes any policy adjustments following a successful execution on `uri`.
+    /// Converts a reference to a cached item into a reference to the
+    /// [`ParsedRecord`] it was created from, if the policy caches records.
+    #[allow(unused_variables)]
+    fn cache_item_to_record(item: &Self::CacheItem) -> Option<&ParsedRecord> {
+        None
+    }
+
+    /// Makes any policy adjustments following a successful execution on
+    /// `uri`, described by `record`.
+    #[allow(unused_variables)]
+    fn note_success(&self, uri: &Uri, record: &AttemptRecord) {}
+
+    /// Makes any policy adjustments following a failed execution on `uri`,
+    /// described by `record`, which either [failed](AttemptStatus::Failed) or
+    /// [timed out](AttemptStatus::TimedOut). Failures that are not the
+    /// target's fault are not noted.
     #[allow(unused_variables)]
-    fn note_success(&self, uri: &Uri) {}
+    fn note_failure(&self, uri: &Uri, record: &AttemptRecord) {}
 
-    /// Makes any policy adjustments following a failed execution on `uri`.
+    /// Makes any policy adjustments following an execution on `uri`,
+    /// described by `record`, being [cancelled](AttemptStatus::Cancelled)
+    /// before it finished, e.g. because a concurrent execution succeeded.
     #[allow(unused_variables)]
-    fn note_failure(&self, uri: &Uri) {}
+    fn note_cancelled(&self, uri: &Uri, record: &AttemptRecord) {}
+
+    /// Makes any policy adjustments as an execution on `uri` starts. Every
+    /// call is followed by a call to [`Policy::note_attempt_end`] once the
+    /// execution finishes or is abandoned.
+    #[allow(unused_variables)]
+    fn note_attempt_start(&self, uri: &Uri) {}
+
+    /// Makes any policy adjustments as an execution on `uri` finishes or is
+    /// abandoned (e.g. because it timed out or another execution succeeded).
+    /// Called before the execution's success or failure is noted.
+    #[allow(unused_variables)]
+    fn note_attempt_end(&self, uri: &Uri) {}
+}
+
+/// Status of an execution on a target, as noted by a [`Policy`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AttemptStatus {
+    /// The execution succeeded.
+    Succeeded,
+    /// The execution failed because of its target.
+    Failed,
+    /// The execution did not finish within its timeout.
+    TimedOut,
+    /// The execution was abandoned before it finished, e.g. because a
+    /// concurrent execution succeeded.
+    Cancelled,
+}
+
+/// Record of an execution on a target, passed to a [`Policy`] as feedback.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AttemptRecord {
+    status: AttemptStatus,
+    elapsed: Duration,
+    attempt: usize,
+    error_class: Option<&'static str>,
+}
+
+impl AttemptRecord {
+    /// Creates a record of an execution.
+    #[must_use]
+    pub const fn new(
+        status: AttemptStatus,
+        elapsed: Duration,
+        attempt: usize,
+        error_class: Option<&'static str>,
+    ) -> Self {
+        Self {
+            status,
+            elapsed,
+            attempt,
+            error_class,
+        }
+    }
+
+    /// Gets the status of the execution.
+    #[must_use]
+    pub const fn status(&self) -> AttemptStatus {
+        self.status
+    }
+
+    /// Gets the time the execution took (until it was abandoned, if it was).
+    #[must_use]
+    pub const fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Gets the index of the execution among all attempts of the operation
+    /// (see [`AttemptContext::attempt`]).
+    ///
+    /// [`AttemptContext::attempt`]: crate::AttemptContext::attempt
+    #[must_use]
+    pub const fn attempt(&self) -> usize {
+        self.attempt
+    }
+
+    /// Gets the class of the execution's error, if supplied by the operation
+    /// (see [`IntoOutcome::error_class`]).
+    ///
+    /// [`IntoOutcome::error_class`]: crate::IntoOutcome::error_class
+    #[must_use]
+    pub const fn error_class(&self) -> Option<&'static str> {
+        self.error_class
+    }
+}
+
+/// Guard for an execution in flight on a target.
+///
+/// Notes the execution's start to a policy when created and its end when
+/// [ended](InFlight::end) or dropped. Executions dropped without being ended
+/// are noted as cancelled.
+pub struct InFlight<'a, P: Policy> {
+    policy: &'a P,
+    uri: Uri,
+    attempt: usize,
+    started: Instant,
+    ended: bool,
+}
+
+impl<'a, P: Policy> InFlight<'a, P> {
+    /// Notes the start of the `attempt`th execution of an operation (see
+    /// [`AttemptRecord::attempt`]), on `uri`, to `policy`.
+    pub fn start(policy: &'a P, uri: Uri, attempt: usize) -> Self {
+        policy.note_attempt_start(&uri);
+        Self {
+            policy,
+            uri,
+            attempt,
+            started: Instant::now(),
+            ended: false,
+        }
+    }
+
+    /// Notes the end of a finished execution, producing the time it took.
+    /// Its outcome is left to be noted by the caller.
+    pub fn end(mut self) -> Duration {
+        self.ended = true;
+        self.started.elapsed()
+    }
+}
+
+impl<P: Policy> Drop for InFlight<'_, P> {
+    fn drop(&mut self) {
+        self.policy.note_attempt_end(&self.uri);
+        if !self.ended {
+            let elapsed = self.started.elapsed();
+            let record = AttemptRecord::new(AttemptStatus::Cancelled, elapsed, self.attempt, None);
+            self.policy.note_cancelled(&self.uri, &record);
+        }
+    }
 }
 
 /// Policy that selects targets based on past successes--if a target was used
-/// successfully in a past execution, it will be recommended first.
+/// successfully in a past execution, it will be recommended first until it
+/// fails.
+///
+/// Other targets are ordered by an inner policy ([`Rfc2782`] by default),
+/// which is passed all feedback on executions as well, so affinity can be
+/// combined with any other policy. The last working target is only preferred
+/// if the inner policy orders it at all (e.g. unless [`OutlierEjection`]
+/// ejected it). Operations routed by key (see [`CallOptions::key`]) are
+/// ordered by the inner policy alone.
+///
+/// By default, the last working target is preferred indefinitely, even once
+/// it is a backup (i.e. of a higher-numbered priority) and the targets it
+/// replaced recover. With [failback](Affinity::failback), a backup is only
+/// preferred for a hold-down period, after which the inner policy's order is
+/// probed again, and with a [TTL](Affinity::ttl), the preference expires if
+/// the target does not succeed for a while.
+///
+/// # Examples
+///
+/// ```
+/// use srv_rs::{SrvClient, policy::{Affinity, RoundRobin}};
+/// use srv_rs::resolver::manual::StaticResolver;
+/// use std::time::Duration;
+///
+/// let policy = Affinity::new(RoundRobin::default())
+///     .failback(Duration::from_secs(60))
+///     .ttl(Duration::from_secs(600));
+/// let resolver = StaticResolver::new_from_single_target("server.example.com", 8080);
+/// let client = SrvClient::<_>::new_with_static_resolver(resolver).policy(policy);
+/// ```
+///
+/// [`CallOptions::key`]: crate::CallOptions::key
 #[derive(Debug, Default)]
-pub struct Affinity {
-    last_working_target: ArcSwapOption<Uri>,
+pub struct Affinity<Inner = Rfc2782> {
+    inner: Inner,
+    failback: Option<Duration>,
+    ttl: Option<Duration>,
+    last_working_target: ArcSwapOption<WorkingTarget>,
+}
+
+/// Target preferred by [`Affinity`].
+#[derive(Debug)]
+struct WorkingTarget {
+    uri: Uri,
+    /// Time the target has been preferred since.
+    since: Instant,
+    /// Time the target last succeeded.
+    last_success: Instant,
+}
+
+impl<Inner> Affinity<Inner> {
+    /// Creates a policy preferring the last working target and ordering
+    /// other targets by `inner`.
+    #[must_use]
+    pub fn new(inner: Inner) -> Self {
+        Self {
+            inner,
+            failback: None,
+            ttl: None,
+            last_working_target: ArcSwapOption::empty(),
+        }
+    }
+
+    /// Sets the hold-down period after which a backup target (i.e. one of a
+    /// higher-numbered priority than some other target) stops being
+    /// preferred, so targets are ordered by the inner policy, which probes
+    /// targets of lower-numbered priorities first. If the backup succeeds
+    /// again instead, it is preferred for another period.
+    ///
+    /// Failback requires the inner policy to cache SRV records (see
+    /// [`Policy::cache_item_to_record`]).
+    #[must_use]
+    pub fn failback(self, hold_down: Duration) -> Self {
+        Self {
+            failback: Some(hold_down),
+            ..self
+        }
+    }
+
+    /// Sets the period after which the last working target stops being
+    /// preferred if it has not succeeded since.
+    #[must_use]
+    pub fn ttl(self, ttl: Duration) -> Self {
+        Self {
+            ttl: Some(ttl),
+            ..self
+        }
+    }
+
+    /// Gets the policy ordering targets other than the last working target.
+    #[must_use]
+    pub const fn inner(&self) -> &Inner {
+        &self.inner
+    }
+
+    fn expired(&self, target: &WorkingTarget, now: Instant) -> bool {
+        self.ttl
+            .is_some_and(|ttl| now.saturating_duration_since(target.last_success) >= ttl)
+    }
+
+    fn held_down(&self, target: &WorkingTarget, now: Instant) -> bool {
+        self.failback
+            .is_some_and(|hold_down| now.saturating_duration_since(target.since) >= hold_down)
+    }
 }
 
 #[async_trait]
-impl Policy for Affinity {
-    type CacheItem = Uri;
-    type Ordering = AffinityUriIter;
+impl<Inner: Policy> Policy for Affinity<Inner> {
+    type CacheItem = Inner::CacheItem;
+    type Ordering = AffinityUriIter<<Vec<usize> as IntoIterator>::IntoIter>;
 
-    async fn refresh_cache<Resolver: SrvResolver>(
+    async fn refresh_cache<Resolver: SrvResolver, P: Policy>(
         &self,
-        client: &SrvClient<Resolver, Self>,
+        client: &SrvClient<Resolver, P>,
     ) -> Result<Cache<Self::CacheItem>, Error<Resolver::Error>> {
-        let (uris, valid_until) = client.get_fresh_uri_candidates().await?;
-        Ok(Cache::new(uris, valid_until))
+        self.inner.refresh_cache(client).await
+    }
+
+    fn order(&self, items: &[Self::CacheItem]) -> Self::Ordering {
+        let now = clock::now();
+        let target = self.last_working_target.load();
+        let target = target
+            .as_deref()
+            .filter(|target| !self.expired(target, now));
+        let order = self.inner.order(items).collect::<Vec<_>>();
+        let preferred = target.and_then(|target| {
+            let idx = items
+                .iter()
+                .position(|item| Inner::cache_item_to_uri(item) == &target.uri)
+                .filter(|idx| order.contains(idx))?;
+            if self.held_down(target, now) {
+                // Fail back if any target has a lower-numbered priority
+                let priority =
+                    |item| Inner::cache_item_to_record(item).map(|record| record.priority);
+                let backup = priority(&items[idx]).is_some_and(|preferred| {
+                    items
+                        .iter()
+                        .filter_map(priority)
+                        .any(|priority| priority < preferred)
+                });
+                if backup {
+                    return None;
+                }
+            }
+            Some(idx)
+        });
+        AffinityUriIter::new(preferred, order.into_iter())
     }
 
-    fn order(&self, uris: &[Uri]) -> Self::Ordering {
-        let preferred = self.last_working_target.load();
-        Self::uris_preferring(uris, preferred.as_deref())
+    fn order_by_key(&self, items: &[Self::CacheItem], key: u64) -> Self::Ordering {
+        let order = self.inner.order_by_key(items, key);
+        AffinityUriIter::new(None, order.collect::<Vec<_>>().into_iter())
     }
 
     fn cache_item_to_uri(item: &Self::CacheItem) -> &Uri {
-        item
+        Inner::cache_item_to_uri(item)
+    }
+
+    fn cache_item_to_record(item: &Self::CacheItem) -> Option<&ParsedRecord> {
+        Inner::cache_item_to_record(item)
+    }
+
+    fn note_success(&self, uri: &Uri, record: &AttemptRecord) {
+        let now = clock::now();
+        let current = self.last_working_target.load();
+        // A target is preferred for another hold-down period if it succeeds
+        // after its preference expired or was held down
+        let since = current
+            .as_deref()
+            .filter(|target| &target.uri == uri)
+            .filter(|target| !self.expired(target, now) && !self.held_down(target, now))
+            .map_or(now, |target| target.since);
+        self.last_working_target.store(Some(Arc::new(WorkingTarget {
+            uri: uri.clone(),
+            since,
+            last_success: now,
+        })));
+        self.inner.note_success(uri, record);
+    }
+
+    fn note_failure(&self, uri: &Uri, record: &AttemptRecord) {
+        let current = self.last_working_target.load();
+        if current.as_deref().is_some_and(|target| &target.uri == uri) {
+            // Unless another target succeeded in the meantime
+            self.last_working_target.compare_and_swap(&current, None);
+        }
+        self.inner.note_failure(uri, record);
+    }
+
+    fn note_cancelled(&self, uri: &Uri, record: &AttemptRecord) {
+        self.inner.note_cancelled(uri, record);
+    }
+
+    fn note_attempt_start(&self, uri: &Uri) {
+        self.inner.note_attempt_start(uri);
     }
 
-    fn note_success(&self, uri: &Uri) {
-        self.last_working_target.store(Some(Arc::new(uri.clone())));
+    fn note_attempt_end(&self, uri: &Uri) {
+        self.inner.note_attempt_end(uri);
     }
 }
 
-impl Affinity {
-    fn uris_preferring(uris: &[Uri], preferred: Option<&Uri>) -> AffinityUriIter {
-        let preferred = preferred
-            .and_then(|preferred| uris.as_ref().iter().position(|uri| uri == preferred))
-            .unwrap_or(0);
-        AffinityUriIter {
-            n: uris.len(),
+/// Iterator over indices of targets based on affinity. See [`Affinity`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AffinityUriIter<Inner> {
+    /// Index of the target to produce first (i.e. the preferred target), if
+    /// there is one.
+    preferred: Option<usize>,
+    /// Whether the preferred target has been produced.
+    produced_preferred: bool,
+    /// Ordering of the inner policy, which the preferred target is skipped in.
+    inner: Inner,
+}
+
+impl<Inner> AffinityUriIter<Inner> {
+    const fn new(preferred: Option<usize>, inner: Inner) -> Self {
+        Self {
             preferred,
-            next: None,
+            produced_preferred: false,
+            inner,
         }
     }
 }
 
-/// Iterator over [`Uri`]s based on affinity. See [`Affinity`].
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
-pub struct AffinityUriIter {
-    /// Number of uris in the cache.
-    n: usize,
-    /// Index of the URI to produce first (i.e. the preferred URI).
-    /// `0` if the first is preferred or there is no preferred URI at all.
-    preferred: usize,
-    /// Index of the next URI to be produced.
-    /// If `None`, the preferred URI will be produced.
-    next: Option<usize>,
-}
-
-impl Iterator for AffinityUriIter {
+impl<Inner: Iterator<Item = usize>> Iterator for AffinityUriIter<Inner> {
     type Item = usize;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let (idx, next) = match self.next {
-            // If no URIs have been produced, produce the preferred URI then go back to the first
-            None => (self.preferred, 0),
-            // If `preferred` is next, skip past it since it was produced already (`self.next != None`)
-            Some(next) if next == self.preferred => (next + 1, next + 2),
-            // Otherwise, advance normally
-            Some(next) => (next, next + 1),
-        };
-        self.next = Some(next);
-        if idx < self.n { Some(idx) } else { None }
+        if !self.produced_preferred {
+            self.produced_preferred = true;
+            if self.preferred.is_some() {
+                return self.preferred;
+            }
+        }
+        let preferred = self.preferred;
+        self.inner.find(|&idx| Some(idx) != preferred)
     }
 }
 
@@ -123,16 +450,86 @@ pub struct Rfc2782;
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct ParsedRecord {
     uri: Uri,
+    target: Target,
     priority: u16,
     weight: u16,
 }
 
 impl ParsedRecord {
-    fn new<Record: SrvRecord>(record: &Record, uri: Uri) -> Self {
-        Self {
+    /// Parses a record with the scheme and path prefix of a client. Its
+    /// [`Target`] is taken from the record itself rather than its URI, so if
+    /// the client's path prefix is invalid, the record is given a URI of only
+    /// its authority (operations passed URIs report the invalid prefix).
+    fn new<Resolver: SrvResolver, P: Policy>(
+        client: &SrvClient<Resolver, P>,
+        record: &Resolver::Record,
+    ) -> Result<Self, http::Error> {
+        let target = Target::from_record(record);
+        let uri = match client.parse_record(record) {
+            Ok(uri) => uri,
+            Err(_) if client.check_path_prefix().is_err() => {
+                Uri::builder().authority(target.to_string()).build()?
+            }
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
             uri,
+            target,
             priority: record.priority(),
             weight: record.weight(),
+        })
+    }
+
+    /// Obtains a refreshed cache of a client's parsed SRV records.
+    async fn refresh_cache<Resolver: SrvResolver, P: Policy>(
+        client: &SrvClient<Resolver, P>,
+    ) -> Result<Cache<Self>, Error<Resolver::Error>> {
+        let (records, valid_until) = client.get_srv_records().await?;
+        let parsed = records
+            .iter()
+            .map(|record| Self::new(client, record))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Cache::new(parsed, valid_until))
+    }
+
+    /// Gets the record's target and port as a [`Uri`].
+    #[must_use]
+    pub const fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// Gets the record's target and port as a [`Target`].
+    #[must_use]
+    pub const fn target(&self) -> &Target {
+        &self.target
+    }
+
+    /// Gets the record's priority.
+    #[must_use]
+    pub const fn priority(&self) -> u16 {
+        self.priority
+    }
+
+    /// Gets the record's weight.
+    #[must_use]
+    pub const fn weight(&self) -> u16 {
+        self.weight
+    }
+}
+
+/// Forgets what a policy tracks about targets no longer among the SRV records.
+fn forget_removed_targets<T>(tracked: &mut HashMap<Uri, T>, records: &[ParsedRecord]) {
+    tracked.retain(|uri, _| records.iter().any(|record| &record.uri == uri));
+}
+
+/// Gets the instant `duration` after `now`, saturating at about as late an
+/// instant as can be represented rather than overflowing.
+fn saturating_after(now: Instant, duration: Duration) -> Instant {
+    let mut duration = duration;
+    loop {
+        match now.checked_add(duration) {
+            Some(later) => return later,
+            None => duration /= 2,
         }
     }
 }
@@ -142,28 +539,116 @@ impl Policy for Rfc2782 {
     type CacheItem = ParsedRecord;
     type Ordering = <Vec<usize> as IntoIterator>::IntoIter;
 
-    async fn refresh_cache<Resolver: SrvResolver>(
+    async fn refresh_cache<Resolver: SrvResolver, P: Policy>(
         &self,
-        client: &SrvClient<Resolver, Self>,
+        client: &SrvClient<Resolver, P>,
     ) -> Result<Cache<Self::CacheItem>, Error<Resolver::Error>> {
-        let (records, valid_until) = client.get_srv_records().await?;
-        let parsed = records
-            .iter()
-            .map(|record| {
-                client
-                    .parse_record(record)
-                    .map(|uri| ParsedRecord::new(record, uri))
-            })
-            .collect::<Result<Vec<_>, _>>()?;
-        Ok(Cache::new(parsed, valid_until))
+        ParsedRecord::refresh_cache(client).await
     }
 
     fn order(&self, records: &[ParsedRecord]) -> Self::Ordering {
         let mut indices = (0..records.len()).collect::<Vec<_>>();
-        let mut rng = rand::rng();
-        indices.sort_by_cached_key(|&idx| {
-            let (priority, weight) = (records[idx].priority, records[idx].weight);
-            crate::record::sort_key(priority, weight, &mut rng)
+        let priority_and_weight = |&idx: &usize| (records[idx].priority, records[idx].weight);
+        crate::record::order_rfc2782(&mut indices, priority_and_weight, rand::rng());
+        indices.into_iter()
+    }
+
+    fn cache_item_to_uri(item: &Self::CacheItem) -> &Uri {
+        &item.uri
+    }
+
+    fn cache_item_to_record(item: &Self::CacheItem) -> Option<&ParsedRecord> {
+        Some(item)
+    }
+}
+
+/// Policy that routes operations with the same key (see [`CallOptions::key`])
+/// to the same target.
+///
+/// Targets are selected by weighted rendezvous hashing within the
+/// lowest-numbered priority, so when a target is added or removed, only the
+/// keys routed to it move. Operations without a key are routed as by
+/// [`Rfc2782`].
+///
+/// # Examples
+///
+/// ```
+/// use srv_rs::{CallOptions, Execution, SrvClient, policy::ConsistentHash};
+/// use srv_rs::resolver::manual::{StaticResolver, StaticSrvRecord};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let records = (0..3).map(|idx| StaticSrvRecord {
+///     target: format!("cache{idx}.example.com"),
+///     port: 11211,
+///     priority: 0,
+///     weight: 10,
+/// });
+/// let resolver = StaticResolver::new(records);
+/// let client = SrvClient::<_, ConsistentHash>::new_with_static_resolver(resolver);
+/// let target = |key| {
+///     client.execute_with_key(Execution::Serial, key, |uri| async move {
+///         Ok::<_, std::io::Error>(uri)
+///     })
+/// };
+/// let first = target("user:42").await.unwrap().unwrap();
+/// assert_eq!(target("user:42").await.unwrap().unwrap(), first);
+/// # }
+/// ```
+///
+/// [`CallOptions::key`]: crate::CallOptions::key
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ConsistentHash;
+
+impl ConsistentHash {
+    /// Relative weight of targets with a weight of zero, which per RFC 2782
+    /// should have a very small chance of being selected.
+    const ZERO_WEIGHT: f64 = 1e-3;
+
+    /// Scores a record for a key per weighted rendezvous hashing, where the
+    /// record with the highest score is selected.
+    fn score(record: &ParsedRecord, key: u64) -> f64 {
+        let target = record
+            .uri
+            .authority()
+            .map_or("", |authority| authority.as_str());
+        let hash = hash(key, target.as_bytes());
+        // Uniformly distributed in (0, 1)
+        #[allow(clippy::cast_precision_loss)]
+        let uniform = ((hash >> 11) as f64 + 0.5) / (1_u64 << 53) as f64;
+        let weight = match record.weight {
+            0 => Self::ZERO_WEIGHT,
+            weight => f64::from(weight),
+        };
+        -weight / uniform.ln()
+    }
+}
+
+#[async_trait]
+impl Policy for ConsistentHash {
+    type CacheItem = ParsedRecord;
+    type Ordering = <Vec<usize> as IntoIterator>::IntoIter;
+
+    async fn refresh_cache<Resolver: SrvResolver, P: Policy>(
+        &self,
+        client: &SrvClient<Resolver, P>,
+    ) -> Result<Cache<Self::CacheItem>, Error<Resolver::Error>> {
+        ParsedRecord::refresh_cache(client).await
+    }
+
+    fn order(&self, records: &[ParsedRecord]) -> Self::Ordering {
+        Rfc2782.order(records)
+    }
+
+    fn order_by_key(&self, records: &[ParsedRecord], key: u64) -> Self::Ordering {
+        let mut indices = (0..records.len()).collect::<Vec<_>>();
+        let scores = records
+            .iter()
+            .map(|record| Self::score(record, key))
+            .collect::<Vec<_>>();
+        indices.sort_by(|&a, &b| {
+            let by_priority = records[a].priority.cmp(&records[b].priority);
+            by_priority.then_with(|| scores[b].total_cmp(&scores[a]))
         });
         indices.into_iter()
     }
@@ -171,56 +656,1484 @@ impl Policy for Rfc2782 {
     fn cache_item_to_uri(item: &Self::CacheItem) -> &Uri {
         &item.uri
     }
+
+    fn cache_item_to_record(item: &Self::CacheItem) -> Option<&ParsedRecord> {
+        Some(item)
+    }
 }
 
-#[test]
-fn affinity_uris_iter_order() {
-    let google: Uri = "https://google.com".parse().unwrap();
-    let amazon: Uri = "https://amazon.com".parse().unwrap();
-    let desco: Uri = "https://deshaw.com".parse().unwrap();
-    let cache = vec![google.clone(), amazon.clone(), desco.clone()];
-    let order = |preferred| {
-        Affinity::uris_preferring(&cache, preferred)
-            .map(|idx| &cache[idx])
-            .collect::<Vec<_>>()
-    };
-    assert_eq!(order(None), vec![&google, &amazon, &desco]);
-    assert_eq!(order(Some(&google)), vec![&google, &amazon, &desco]);
-    assert_eq!(order(Some(&amazon)), vec![&amazon, &google, &desco]);
-    assert_eq!(order(Some(&desco)), vec![&desco, &google, &amazon]);
-}
-
-#[test]
-fn balance_uris_iter_order() {
-    // Clippy doesn't like that Uri has interior mutability and is being used
-    // as a HashMap key but we aren't doing anything naughty in the test
-    #[allow(clippy::mutable_key_type)]
-    let mut priorities = std::collections::HashMap::new();
-    priorities.insert("https://google.com".parse::<Uri>().unwrap(), 2);
-    priorities.insert("https://cloudflare.com".parse().unwrap(), 2);
-    priorities.insert("https://amazon.com".parse().unwrap(), 1);
-    priorities.insert("https://deshaw.com".parse().unwrap(), 1);
-
-    let cache = priorities
+/// Hashes `bytes` with a seed, stably across processes and platforms so that
+/// every client routes keys alike (FNV-1a followed by the `SplitMix64`
+/// finalizer, which spreads small input differences over every output bit).
+pub(crate) fn hash(seed: u64, bytes: &[u8]) -> u64 {
+    let mut hash = bytes
         .iter()
-        .map(|(uri, &priority)| ParsedRecord {
-            uri: uri.clone(),
-            priority,
-            weight: u16::from(rand::random::<u8>()),
-        })
-        .collect::<Vec<_>>();
+        .fold(0xcbf2_9ce4_8422_2325 ^ seed, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+/// Policy that ejects targets failing repeatedly (passive outlier detection),
+/// ordering the remaining targets as by [`Rfc2782`].
+///
+/// A target that fails a number of consecutive times is left out of the
+/// order for an ejection period, which doubles each time the target is
+/// ejected again without succeeding in between. At most a fraction of the
+/// targets are ejected at once, and if every target is ejected, they are all
+/// ordered as a last resort. Ejections last across cache refreshes for
+/// targets still among the SRV records.
+///
+/// # Examples
+///
+/// ```
+/// use srv_rs::{SrvClient, policy::OutlierEjection};
+/// use srv_rs::resolver::manual::StaticResolver;
+/// use std::time::Duration;
+///
+/// let policy = OutlierEjection::new()
+///     .consecutive_failures(3)
+///     .ejection(Duration::from_secs(10), Duration::from_secs(300))
+///     .max_ejected_percent(50);
+/// let resolver = StaticResolver::new_from_single_target("server.example.com", 8080);
+/// let client = SrvClient::<_>::new_with_static_resolver(resolver).policy(policy);
+/// ```
+#[derive(Debug)]
+pub struct OutlierEjection {
+    consecutive_failures: u32,
+    base_ejection: Duration,
+    max_ejection: Duration,
+    max_ejected_percent: u8,
+    state: Mutex<EjectionState>,
+}
+
+/// Health of a client's targets, as tracked by [`OutlierEjection`].
+#[derive(Debug, Default)]
+struct EjectionState {
+    /// Number of targets in the client's cache.
+    targets: usize,
+    health: HashMap<Uri, TargetHealth>,
+}
+
+/// Health of a single target, as tracked by [`OutlierEjection`].
+#[derive(Debug, Default)]
+struct TargetHealth {
+    consecutive_failures: u32,
+    /// Number of times the target was ejected since it last succeeded.
+    ejections: u32,
+    ejected_until: Option<Instant>,
+}
+
+impl TargetHealth {
+    fn ejected(&self, now: Instant) -> bool {
+        self.ejected_until.is_some_and(|until| now < until)
+    }
+}
+
+impl OutlierEjection {
+    /// Creates a policy ejecting targets after 5 consecutive failures, for 30
+    /// seconds doubling up to 5 minutes, with at most half of the targets
+    /// ejected at once.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            consecutive_failures: 5,
+            base_ejection: Duration::from_secs(30),
+            max_ejection: Duration::from_secs(300),
+            max_ejected_percent: 50,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Sets the number of consecutive failures after which a target is
+    /// ejected. A value of zero is treated as one.
+    #[must_use]
+    pub fn consecutive_failures(self, consecutive_failures: u32) -> Self {
+        Self {
+            consecutive_failures: consecutive_failures.max(1),
+            ..self
+        }
+    }
+
+    /// Sets the period a target is first ejected for, which doubles for every
+    /// subsequent ejection up to `max_ejection`.
+    #[must_use]
+    pub fn ejection(self, base_ejection: Duration, max_ejection: Duration) -> Self {
+        Self {
+            base_ejection,
+            max_ejection,
+            ..self
+        }
+    }
+
+    /// Sets the maximum percentage of targets (rounded down) ejected at once.
+    /// Values above 100 are treated as 100.
+    #[must_use]
+    pub fn max_ejected_percent(self, max_ejected_percent: u8) -> Self {
+        Self {
+            max_ejected_percent: max_ejected_percent.min(100),
+            ..self
+        }
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, EjectionState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for OutlierEjection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-    let ordered = |iter: <Rfc2782 as Policy>::Ordering| {
-        let mut last = None;
-        for item in iter.map(|idx| &cache[idx]) {
-            if let Some(last) = last {
-                assert!(priorities[last] <= priorities[&item.uri]);
+#[async_trait]
+impl Policy for OutlierEjection {
+    type CacheItem = ParsedRecord;
+    type Ordering = <Vec<usize> as IntoIterator>::IntoIter;
+
+    async fn refresh_cache<Resolver: SrvResolver, P: Policy>(
+        &self,
+        client: &SrvClient<Resolver, P>,
+    ) -> Result<Cache<Self::CacheItem>, Error<Resolver::Error>> {
+        let cache = ParsedRecord::refresh_cache(client).await?;
+        let mut state = self.lock_state();
+        state.targets = cache.items().len();
+        forget_removed_targets(&mut state.health, cache.items());
+        drop(state);
+        Ok(cache)
+    }
+
+    fn order(&self, records: &[ParsedRecord]) -> Self::Ordering {
+        let now = clock::now();
+        let state = self.lock_state();
+        let (ejected, order): (Vec<_>, Vec<_>) = Rfc2782.order(records).partition(|&idx| {
+            let health = state.health.get(&records[idx].uri);
+            health.is_some_and(|health| health.ejected(now))
+        });
+        drop(state);
+        // Ejected targets are better than none (e.g. with every target ejected)
+        if order.is_empty() { ejected } else { order }.into_iter()
+    }
+
+    fn cache_item_to_uri(item: &Self::CacheItem) -> &Uri {
+        &item.uri
+    }
+
+    fn cache_item_to_record(item: &Self::CacheItem) -> Option<&ParsedRecord> {
+        Some(item)
+    }
+
+    fn note_success(&self, uri: &Uri, _record: &AttemptRecord) {
+        self.lock_state().health.remove(uri);
+    }
+
+    fn note_failure(&self, uri: &Uri, _record: &AttemptRecord) {
+        let now = clock::now();
+        let mut state = self.lock_state();
+        let max_ejected = state.targets * usize::from(self.max_ejected_percent) / 100;
+        let ejected = state.health.values().filter(|h| h.ejected(now)).count();
+        let health = state.health.entry(uri.clone()).or_default();
+        health.consecutive_failures += 1;
+        if health.consecutive_failures >= self.consecutive_failures
+            && !health.ejected(now)
+            && ejected < max_ejected
+        {
+            let factor = 1_u32.checked_shl(health.ejections).unwrap_or(u32::MAX);
+            let ejection = self.base_ejection.saturating_mul(factor);
+            let ejection = ejection.min(self.max_ejection);
+            #[cfg(feature = "log")]
+            tracing::info!(URI = %uri, ?ejection, "ejecting failing target");
+            health.consecutive_failures = 0;
+            health.ejections = health.ejections.saturating_add(1);
+            health.ejected_until = Some(saturating_after(now, ejection));
+        }
+        drop(state);
+    }
+}
+
+/// Policy that selects the targets with the fewest executions in flight
+/// (least outstanding requests) first.
+///
+/// Targets are ordered by priority, then by their number of executions in
+/// flight, then by weight (heaviest first), with remaining ties broken at
+/// random. This spreads concurrent executions across targets, unlike the
+/// random ordering of [`Rfc2782`], which may pile them onto one target.
+#[derive(Debug, Default)]
+pub struct LeastLoaded {
+    in_flight: Mutex<HashMap<Uri, usize>>,
+}
+
+impl LeastLoaded {
+    /// Gets the number of executions in flight on `uri`.
+    pub fn in_flight(&self, uri: &Uri) -> usize {
+        self.lock_in_flight().get(uri).copied().unwrap_or_default()
+    }
+
+    fn lock_in_flight(&self) -> MutexGuard<'_, HashMap<Uri, usize>> {
+        self.in_flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl Policy for LeastLoaded {
+    type CacheItem = ParsedRecord;
+    type Ordering = <Vec<usize> as IntoIterator>::IntoIter;
+
+    async fn refresh_cache<Resolver: SrvResolver, P: Policy>(
+        &self,
+        client: &SrvClient<Resolver, P>,
+    ) -> Result<Cache<Self::CacheItem>, Error<Resolver::Error>> {
+        ParsedRecord::refresh_cache(client).await
+    }
+
+    fn order(&self, records: &[ParsedRecord]) -> Self::Ordering {
+        let mut rng = rand::rng();
+        let in_flight = self.lock_in_flight();
+        let mut keys = records
+            .iter()
+            .enumerate()
+            .map(|(idx, record)| {
+                let load = in_flight.get(&record.uri).copied().unwrap_or_default();
+                let key = (
+                    record.priority,
+                    load,
+                    Reverse(record.weight),
+                    rng.random::<u32>(),
+                );
+                (key, idx)
+            })
+            .collect::<Vec<_>>();
+        drop(in_flight);
+        keys.sort_unstable();
+        keys.into_iter()
+            .map(|(_, idx)| idx)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn cache_item_to_uri(item: &Self::CacheItem) -> &Uri {
+        &item.uri
+    }
+
+    fn cache_item_to_record(item: &Self::CacheItem) -> Option<&ParsedRecord> {
+        Some(item)
+    }
+
+    fn note_attempt_start(&self, uri: &Uri) {
+        *self.lock_in_flight().entry(uri.clone()).or_default() += 1;
+    }
+
+    fn note_attempt_end(&self, uri: &Uri) {
+        let mut in_flight = self.lock_in_flight();
+        if let Some(count) = in_flight.get_mut(uri) {
+            *count -= 1;
+            if *count == 0 {
+                in_flight.remove(uri);
             }
-            last = Some(&item.uri);
         }
-    };
+        drop(in_flight);
+    }
+}
+
+/// Policy that selects the targets with the lowest expected latency first,
+/// using a peak-sensitive exponentially weighted moving average (Peak-EWMA).
+///
+/// The latency of each target is estimated from the durations of past
+/// executions on it, decaying the weight of older durations over time but
+/// immediately jumping to any duration above the estimate. A target's cost is
+/// its estimate multiplied by its number of executions in flight (plus one),
+/// and targets are ordered by priority, then by cost, then by number of
+/// executions in flight (e.g. between targets yet to be used, whose cost is
+/// zero), with remaining ties broken at random. Failures count as lasting at
+/// least a penalty duration, so targets failing fast are not preferred, and
+/// cancelled executions count as lasting at least as long as they ran, so
+/// only ever raise the estimate.
+///
+/// # Examples
+///
+/// ```
+/// use srv_rs::{SrvClient, policy::PeakEwma};
+/// use srv_rs::resolver::manual::StaticResolver;
+/// use std::time::Duration;
+///
+/// let policy = PeakEwma::new()
+///     .decay(Duration::from_secs(5))
+///     .failure_penalty(Duration::from_secs(2));
+/// let resolver = StaticResolver::new_from_single_target("server.example.com", 8080);
+/// let client = SrvClient::<_>::new_with_static_resolver(resolver).policy(policy);
+/// ```
+#[derive(Debug)]
+pub struct PeakEwma {
+    decay: Duration,
+    failure_penalty: Duration,
+    latencies: Mutex<HashMap<Uri, Latency>>,
+}
+
+/// Latency of a single target, as tracked by [`PeakEwma`].
+#[derive(Debug, Default)]
+struct Latency {
+    /// Estimated latency, in seconds.
+    estimate: f64,
+    /// Time the estimate was last updated, if ever.
+    updated: Option<Instant>,
+    in_flight: usize,
+}
+
+impl Latency {
+    fn cost(&self) -> f64 {
+        #[allow(clippy::cast_precision_loss)]
+        let load = (self.in_flight + 1) as f64;
+        self.estimate * load
+    }
+
+    fn observe(&mut self, duration: Duration, decay: Duration) {
+        let now = clock::now();
+        let duration = duration.as_secs_f64();
+        self.estimate = match self.updated {
+            Some(updated) if duration < self.estimate => {
+                let elapsed = now.saturating_duration_since(updated).as_secs_f64();
+                let weight = (-elapsed / decay.as_secs_f64()).exp();
+                self.estimate.mul_add(weight, duration * (1.0 - weight))
+            }
+            // Jump to the first duration, and to peaks
+            _ => duration,
+        };
+        self.updated = Some(now);
+    }
+
+    /// Observes a duration that an execution lasted at least (e.g. before
+    /// being cancelled), which counts only if it is a peak.
+    fn observe_at_least(&mut self, duration: Duration, decay: Duration) {
+        if self.updated.is_none() || duration.as_secs_f64() >= self.estimate {
+            self.observe(duration, decay);
+        }
+    }
+}
+
+impl PeakEwma {
+    /// Creates a policy with a decay time of 10 seconds and a failure
+    /// penalty of 1 second.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            decay: Duration::from_secs(10),
+            failure_penalty: Duration::from_secs(1),
+            latencies: Mutex::default(),
+        }
+    }
+
+    /// Sets the time over which the weight of a duration in a target's
+    /// latency estimate decays by a factor of *e*.
+    #[must_use]
+    pub fn decay(self, decay: Duration) -> Self {
+        Self { decay, ..self }
+    }
+
+    /// Sets the minimum duration failures count as lasting.
+    #[must_use]
+    pub fn failure_penalty(self, failure_penalty: Duration) -> Self {
+        Self {
+            failure_penalty,
+            ..self
+        }
+    }
+
+    /// Gets the estimated latency of `uri`, if it has been used.
+    pub fn estimate(&self, uri: &Uri) -> Option<Duration> {
+        let latencies = self.lock_latencies();
+        let estimate = latencies
+            .get(uri)
+            .and_then(|latency| latency.updated.map(|_| latency.estimate));
+        drop(latencies);
+        estimate.map(Duration::from_secs_f64)
+    }
+
+    fn lock_latencies(&self) -> MutexGuard<'_, HashMap<Uri, Latency>> {
+        self.latencies
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for PeakEwma {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Policy for PeakEwma {
+    type CacheItem = ParsedRecord;
+    type Ordering = <Vec<usize> as IntoIterator>::IntoIter;
+
+    async fn refresh_cache<Resolver: SrvResolver, P: Policy>(
+        &self,
+        client: &SrvClient<Resolver, P>,
+    ) -> Result<Cache<Self::CacheItem>, Error<Resolver::Error>> {
+        let cache = ParsedRecord::refresh_cache(client).await?;
+        forget_removed_targets(&mut self.lock_latencies(), cache.items());
+        Ok(cache)
+    }
+
+    fn order(&self, records: &[ParsedRecord]) -> Self::Ordering {
+        let mut rng = rand::rng();
+        let latencies = self.lock_latencies();
+        let mut keys = records
+            .iter()
+            .enumerate()
+            .map(|(idx, record)| {
+                let latency = latencies.get(&record.uri);
+                let cost = latency.map_or(0.0, Latency::cost);
+                let load = latency.map_or(0, |latency| latency.in_flight);
+                (record.priority, cost, (load, rng.random::<u32>()), idx)
+            })
+            .collect::<Vec<_>>();
+        drop(latencies);
+        keys.sort_unstable_by(|a, b| {
+            a.0.cmp(&b.0)
+                .then_with(|| a.1.total_cmp(&b.1))
+                .then_with(|| a.2.cmp(&b.2))
+        });
+        keys.into_iter()
+            .map(|(.., idx)| idx)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn cache_item_to_uri(item: &Self::CacheItem) -> &Uri {
+        &item.uri
+    }
+
+    fn cache_item_to_record(item: &Self::CacheItem) -> Option<&ParsedRecord> {
+        Some(item)
+    }
+
+    fn note_success(&self, uri: &Uri, record: &AttemptRecord) {
+        let mut latencies = self.lock_latencies();
+        let latency = latencies.entry(uri.clone()).or_default();
+        latency.observe(record.elapsed(), self.decay);
+        drop(latencies);
+    }
+
+    fn note_failure(&self, uri: &Uri, record: &AttemptRecord) {
+        let mut latencies = self.lock_latencies();
+        let latency = latencies.entry(uri.clone()).or_default();
+        latency.observe(record.elapsed().max(self.failure_penalty), self.decay);
+        drop(latencies);
+    }
+
+    fn note_cancelled(&self, uri: &Uri, record: &AttemptRecord) {
+        let mut latencies = self.lock_latencies();
+        let latency = latencies.entry(uri.clone()).or_default();
+        latency.observe_at_least(record.elapsed(), self.decay);
+        drop(latencies);
+    }
+
+    fn note_attempt_start(&self, uri: &Uri) {
+        self.lock_latencies()
+            .entry(uri.clone())
+            .or_default()
+            .in_flight += 1;
+    }
+
+    fn note_attempt_end(&self, uri: &Uri) {
+        if let Some(latency) = self.lock_latencies().get_mut(uri) {
+            latency.in_flight = latency.in_flight.saturating_sub(1);
+        }
+    }
+}
+
+/// Policy that samples two targets and selects the less loaded (or faster)
+/// one first, known as the power of two choices.
+///
+/// The two targets are sampled from the lowest-numbered priority with a
+/// healthy target, with probability proportional to their weight, and the one
+/// with the lower cost (as estimated by [`PeakEwma`]) is ordered first. The
+/// remaining targets follow as fallbacks, healthy targets before unhealthy
+/// ones, each ordered as by [`Rfc2782`]. A target is unhealthy for a period
+/// after it fails, while a cancelled execution counts towards its cost as
+/// lasting at least as long as it ran. The fallbacks are only ordered once
+/// they are reached, so executions that succeed on a sampled target do not
+/// pay for ordering them.
+///
+/// # Examples
+///
+/// ```
+/// use srv_rs::{SrvClient, policy::P2C};
+/// use srv_rs::resolver::manual::StaticResolver;
+/// use std::time::Duration;
+///
+/// let policy = P2C::new()
+///     .decay(Duration::from_secs(5))
+///     .unhealthy_for(Duration::from_secs(30));
+/// let resolver = StaticResolver::new_from_single_target("server.example.com", 8080);
+/// let client = SrvClient::<_>::new_with_static_resolver(resolver).policy(policy);
+/// ```
+#[derive(Debug)]
+pub struct P2C {
+    decay: Duration,
+    unhealthy_for: Duration,
+    stats: Mutex<HashMap<Uri, TargetStats>>,
+}
+
+/// Load and health of a single target, as tracked by [`P2C`].
+#[derive(Debug, Default)]
+struct TargetStats {
+    latency: Latency,
+    unhealthy_until: Option<Instant>,
+}
+
+impl TargetStats {
+    fn healthy(&self, now: Instant) -> bool {
+        self.unhealthy_until.is_none_or(|until| until <= now)
+    }
+}
+
+impl P2C {
+    /// Creates a policy with a decay time of 10 seconds, under which targets
+    /// are unhealthy for 10 seconds after failing.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            decay: Duration::from_secs(10),
+            unhealthy_for: Duration::from_secs(10),
+            stats: Mutex::default(),
+        }
+    }
+
+    /// Sets the time over which the weight of a duration in a target's
+    /// latency estimate decays by a factor of *e* (see [`PeakEwma::decay`]).
+    #[must_use]
+    pub fn decay(self, decay: Duration) -> Self {
+        Self { decay, ..self }
+    }
+
+    /// Sets the period a target is unhealthy for after it fails.
+    #[must_use]
+    pub fn unhealthy_for(self, unhealthy_for: Duration) -> Self {
+        Self {
+            unhealthy_for,
+            ..self
+        }
+    }
+
+    fn lock_stats(&self) -> MutexGuard<'_, HashMap<Uri, TargetStats>> {
+        self.stats.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Samples a target among `candidates` (other than `excluded`) with
+    /// probability proportional to its weight, or uniformly if every
+    /// candidate's weight is zero.
+    fn sample(
+        records: &[ParsedRecord],
+        candidates: impl Iterator<Item = usize> + Clone,
+        excluded: Option<usize>,
+        rng: &mut impl Rng,
+    ) -> Option<usize> {
+        let mut candidates = candidates.filter(|&idx| Some(idx) != excluded);
+        let total: u32 = candidates
+            .clone()
+            .map(|idx| u32::from(records[idx].weight))
+            .sum();
+        if total == 0 {
+            let count = candidates.clone().count();
+            return (count > 0)
+                .then(|| rng.random_range(0..count))
+                .and_then(|nth| candidates.nth(nth));
+        }
+        let mut point = rng.random_range(0..total);
+        candidates.find(|&idx| {
+            let weight = u32::from(records[idx].weight);
+            point.checked_sub(weight).map(|rest| point = rest).is_none()
+        })
+    }
+}
+
+impl Default for P2C {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Policy for P2C {
+    type CacheItem = ParsedRecord;
+    type Ordering = P2cOrdering;
+
+    async fn refresh_cache<Resolver: SrvResolver, P: Policy>(
+        &self,
+        client: &SrvClient<Resolver, P>,
+    ) -> Result<Cache<Self::CacheItem>, Error<Resolver::Error>> {
+        let mut cache = ParsedRecord::refresh_cache(client).await?;
+        // Records are kept sorted by priority so each priority is contiguous
+        if !cache.items().is_sorted_by_key(|record| record.priority) {
+            let mut records = cache.items().to_vec();
+            records.sort_by_key(|record| record.priority);
+            cache = Cache::new(records, cache.valid_until());
+        }
+        forget_removed_targets(&mut self.lock_stats(), cache.items());
+        Ok(cache)
+    }
+
+    fn order(&self, records: &[ParsedRecord]) -> Self::Ordering {
+        let now = clock::now();
+        let stats = self.lock_stats();
+        let healthy = |idx: usize| {
+            let stats = stats.get(&records[idx].uri);
+            stats.is_none_or(|stats| stats.healthy(now))
+        };
+        // Fall back to the lowest-numbered priority if no target is healthy
+        let any_healthy = (0..records.len()).any(healthy);
+        let candidate = |idx: usize| !any_healthy || healthy(idx);
+        let Some(start) = (0..records.len()).find(|&idx| candidate(idx)) else {
+            return P2cOrdering::new([None, None], Vec::new());
+        };
+        let priority = records[start].priority;
+        let candidates = (start..records.len())
+            .take_while(|&idx| records[idx].priority == priority)
+            .filter(|&idx| candidate(idx));
+        let mut rng = rand::rng();
+        let first = Self::sample(records, candidates.clone(), None, &mut rng);
+        let second = Self::sample(records, candidates, first, &mut rng);
+        let cost = |idx: usize| {
+            let latency = stats.get(&records[idx].uri).map(|stats| &stats.latency);
+            let load = latency.map_or(0, |latency| latency.in_flight);
+            (latency.map_or(0.0, Latency::cost), load)
+        };
+        // Ties are left in the (random) order the targets were sampled in
+        let swap = first
+            .zip(second)
+            .is_some_and(|(first, second)| cost(second) < cost(first));
+        let picks = if swap {
+            [second, first]
+        } else {
+            [first, second]
+        };
+        let targets = (0..records.len())
+            .map(|idx| (records[idx].priority, records[idx].weight, healthy(idx)))
+            .collect();
+        drop(stats);
+        P2cOrdering::new(picks, targets)
+    }
+
+    fn cache_item_to_uri(item: &Self::CacheItem) -> &Uri {
+        &item.uri
+    }
+
+    fn cache_item_to_record(item: &Self::CacheItem) -> Option<&ParsedRecord> {
+        Some(item)
+    }
+
+    fn note_success(&self, uri: &Uri, record: &AttemptRecord) {
+        let mut stats = self.lock_stats();
+        let target = stats.entry(uri.clone()).or_default();
+        target.latency.observe(record.elapsed(), self.decay);
+        target.unhealthy_until = None;
+        drop(stats);
+    }
+
+    fn note_failure(&self, uri: &Uri, _record: &AttemptRecord) {
+        let unhealthy_until = saturating_after(clock::now(), self.unhealthy_for);
+        let mut stats = self.lock_stats();
+        stats.entry(uri.clone()).or_default().unhealthy_until = Some(unhealthy_until);
+        drop(stats);
+    }
+
+    fn note_cancelled(&self, uri: &Uri, record: &AttemptRecord) {
+        let mut stats = self.lock_stats();
+        let target = stats.entry(uri.clone()).or_default();
+        target
+            .latency
+            .observe_at_least(record.elapsed(), self.decay);
+        drop(stats);
+    }
+
+    fn note_attempt_start(&self, uri: &Uri) {
+        self.lock_stats()
+            .entry(uri.clone())
+            .or_default()
+            .latency
+            .in_flight += 1;
+    }
+
+    fn note_attempt_end(&self, uri: &Uri) {
+        if let Some(stats) = self.lock_stats().get_mut(uri) {
+            stats.latency.in_flight = stats.latency.in_flight.saturating_sub(1);
+        }
+    }
+}
+
+/// Iterator over the indices of targets selected by [`P2C`]: the sampled
+/// targets, then every other target as a fallback, which are only ordered once
+/// the sampled targets have been produced.
+#[derive(Clone, Debug)]
+pub struct P2cOrdering {
+    /// Sampled targets, in the order they are produced.
+    picks: [Option<usize>; 2],
+    /// Number of sampled targets produced so far.
+    produced: usize,
+    /// Priority, weight, and health of every target, to order fallbacks by.
+    targets: Vec<(u16, u16, bool)>,
+    /// Fallbacks, once ordered.
+    fallbacks: Option<std::vec::IntoIter<usize>>,
+}
+
+impl P2cOrdering {
+    const fn new(picks: [Option<usize>; 2], targets: Vec<(u16, u16, bool)>) -> Self {
+        Self {
+            picks,
+            produced: 0,
+            targets,
+            fallbacks: None,
+        }
+    }
+
+    /// Orders the targets other than the sampled ones, healthy targets before
+    /// unhealthy ones, each as by [`Rfc2782`].
+    fn order_fallbacks(&self) -> std::vec::IntoIter<usize> {
+        let mut fallbacks = (0..self.targets.len())
+            .filter(|&idx| !self.picks.contains(&Some(idx)))
+            .collect::<Vec<_>>();
+        let priority_and_weight = |&idx: &usize| (self.targets[idx].0, self.targets[idx].1);
+        crate::record::order_rfc2782(&mut fallbacks, priority_and_weight, &mut rand::rng());
+        fallbacks.sort_by_key(|&idx| !self.targets[idx].2);
+        fallbacks.into_iter()
+    }
+}
+
+impl Iterator for P2cOrdering {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(&pick) = self.picks.get(self.produced) {
+            self.produced += 1;
+            if pick.is_some() {
+                return pick;
+            }
+        }
+        if self.fallbacks.is_none() {
+            self.fallbacks = Some(self.order_fallbacks());
+        }
+        self.fallbacks.as_mut()?.next()
+    }
+}
+
+/// Policy that rotates through the targets of the lowest-numbered priority in
+/// proportion to their weight, using the smooth weighted round-robin
+/// algorithm of nginx.
+///
+/// Each time targets are ordered, every target of the lowest-numbered priority
+/// gains its weight, and the target with the most accumulated weight is
+/// selected first and loses the priority's total weight. This interleaves
+/// targets evenly, e.g. targets weighted 5, 1, and 1 are selected in the
+/// order `a a b a c a a`. Targets of weight zero are only selected if every
+/// target of their priority has weight zero, in which case they rotate
+/// equally. The remaining targets follow the selected target by priority,
+/// then by accumulated weight, as fallbacks. The rotation carries over across
+/// cache refreshes as long as the SRV records are unchanged.
+///
+/// # Examples
+///
+/// ```
+/// use srv_rs::{SrvClient, policy::RoundRobin};
+/// use srv_rs::resolver::manual::StaticResolver;
+///
+/// let resolver = StaticResolver::new_from_single_target("server.example.com", 8080);
+/// let client = SrvClient::<_, RoundRobin>::new_with_static_resolver(resolver);
+/// ```
+#[derive(Debug, Default)]
+pub struct RoundRobin {
+    state: Mutex<RotationState>,
+}
+
+/// Rotation through a client's targets, as tracked by [`RoundRobin`].
+#[derive(Debug, Default)]
+struct RotationState {
+    /// Records the rotation is over.
+    records: HashSet<ParsedRecord>,
+    /// Weight accumulated by each target.
+    current: HashMap<Uri, i64>,
+}
+
+impl RoundRobin {
+    fn lock_state(&self) -> MutexGuard<'_, RotationState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl Policy for RoundRobin {
+    type CacheItem = ParsedRecord;
+    type Ordering = <Vec<usize> as IntoIterator>::IntoIter;
+
+    async fn refresh_cache<Resolver: SrvResolver, P: Policy>(
+        &self,
+        client: &SrvClient<Resolver, P>,
+    ) -> Result<Cache<Self::CacheItem>, Error<Resolver::Error>> {
+        let cache = ParsedRecord::refresh_cache(client).await?;
+        let records = cache.items().iter().cloned().collect::<HashSet<_>>();
+        let mut state = self.lock_state();
+        // Restart the rotation if the records changed
+        if state.records != records {
+            state.records = records;
+            state.current.clear();
+        }
+        drop(state);
+        Ok(cache)
+    }
+
+    fn order(&self, records: &[ParsedRecord]) -> Self::Ordering {
+        let Some(priority) = records.iter().map(|record| record.priority).min() else {
+            return Vec::new().into_iter();
+        };
+        let tier = || (0..records.len()).filter(move |&idx| records[idx].priority == priority);
+        let equal = tier().all(|idx| records[idx].weight == 0);
+        let weight = |idx: usize| match records[idx].weight {
+            _ if equal => 1,
+            weight => i64::from(weight),
+        };
+        let total = tier().map(weight).sum::<i64>();
+        let mut state = self.lock_state();
+        // Ties are broken by weight, then by target, independently of the
+        // order of the records (which resolvers may shuffle on each refresh)
+        let target = |idx: usize| {
+            let authority = records[idx].uri.authority();
+            Reverse(authority.map_or("", |authority| authority.as_str()))
+        };
+        let mut selected = None;
+        for idx in tier() {
+            let current = state.current.entry(records[idx].uri.clone()).or_default();
+            *current += weight(idx);
+            let key = (*current, records[idx].weight, target(idx));
+            if selected.as_ref().is_none_or(|(_, max)| key > *max) {
+                selected = Some((idx, key));
+            }
+        }
+        let selected = selected.map(|(idx, _)| idx);
+        if let Some(idx) = selected {
+            if let Some(current) = state.current.get_mut(&records[idx].uri) {
+                *current -= total;
+            }
+        }
+        let mut keys = records
+            .iter()
+            .enumerate()
+            .map(|(idx, record)| {
+                let current = state.current.get(&record.uri).copied().unwrap_or_default();
+                let key = (
+                    Some(idx) != selected,
+                    record.priority,
+                    Reverse(current),
+                    Reverse(record.weight),
+                    Reverse(target(idx)),
+                );
+                (key, idx)
+            })
+            .collect::<Vec<_>>();
+        drop(state);
+        keys.sort_unstable();
+        keys.into_iter()
+            .map(|(_, idx)| idx)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn cache_item_to_uri(item: &Self::CacheItem) -> &Uri {
+        &item.uri
+    }
+
+    fn cache_item_to_record(item: &Self::CacheItem) -> Option<&ParsedRecord> {
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn affinity_uris_iter_order() {
+        let mut records = weighted_records(&[0, 0, 0]);
+        for (priority, record) in (0..).zip(&mut records) {
+            record.priority = priority;
+        }
+        let uris = records.iter().map(|record| &record.uri).collect::<Vec<_>>();
+        let policy = Affinity::<Rfc2782>::default();
+        let order = |preferred: Option<&Uri>| {
+            let success = AttemptRecord::new(AttemptStatus::Succeeded, Duration::ZERO, 0, None);
+            if let Some(preferred) = preferred {
+                policy.note_success(preferred, &success);
+            }
+            policy
+                .order(&records)
+                .map(|idx| uris[idx])
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(order(None), [uris[0], uris[1], uris[2]]);
+        assert_eq!(order(Some(uris[0])), [uris[0], uris[1], uris[2]]);
+        assert_eq!(order(Some(uris[1])), [uris[1], uris[0], uris[2]]);
+        assert_eq!(order(Some(uris[2])), [uris[2], uris[0], uris[1]]);
+        // Operations routed by key are ordered by the inner policy alone
+        let by_key = policy.order_by_key(&records, 0).collect::<Vec<_>>();
+        assert_eq!(by_key, [0, 1, 2]);
+    }
+
+    #[test]
+    fn balance_uris_iter_order() {
+        // Clippy doesn't like that Uri has interior mutability and is being used
+        // as a HashMap key but we aren't doing anything naughty in the test
+        #[allow(clippy::mutable_key_type)]
+        let mut priorities = std::collections::HashMap::new();
+        priorities.insert("https://google.com".parse::<Uri>().unwrap(), 2);
+        priorities.insert("https://cloudflare.com".parse().unwrap(), 2);
+        priorities.insert("https://amazon.com".parse().unwrap(), 1);
+        priorities.insert("https://deshaw.com".parse().unwrap(), 1);
+
+        let cache = priorities
+            .iter()
+            .map(|(uri, &priority)| ParsedRecord {
+                uri: uri.clone(),
+                target: Target::from_uri(uri),
+                priority,
+                weight: u16::from(rand::random::<u8>()),
+            })
+            .collect::<Vec<_>>();
+
+        let ordered = |iter: <Rfc2782 as Policy>::Ordering| {
+            let mut last = None;
+            for item in iter.map(|idx| &cache[idx]) {
+                if let Some(last) = last {
+                    assert!(priorities[last] <= priorities[&item.uri]);
+                }
+                last = Some(&item.uri);
+            }
+        };
+
+        for _ in 0..5 {
+            ordered(Rfc2782.order(&cache));
+        }
+    }
+
+    fn weighted_records(weights: &[u16]) -> Vec<ParsedRecord> {
+        use crate::resolver::manual::StaticSrvRecord;
+        let records = StaticSrvRecord::numbered(weights.len().try_into().unwrap());
+        records
+            .iter()
+            .zip(weights)
+            .map(|(record, &weight)| ParsedRecord {
+                uri: record.parse("https", "/").unwrap(),
+                target: Target::from_record(record),
+                priority: 0,
+                weight,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn consistent_hash_moves_few_keys() {
+        let records = weighted_records(&[1, 1, 1, 1, 1]);
+        let target = |records: &[ParsedRecord], key: u64| {
+            let idx = ConsistentHash.order_by_key(records, key).next().unwrap();
+            records[idx].uri.clone()
+        };
+        let keys = (0..1000_u32).map(|key| hash(0, &key.to_be_bytes()));
+        let before = keys
+            .clone()
+            .map(|key| target(&records, key))
+            .collect::<Vec<_>>();
+        // The same key is routed to the same target
+        assert!(
+            keys.clone()
+                .zip(&before)
+                .all(|(key, uri)| target(&records, key) == *uri)
+        );
+
+        let removed = &records[2].uri;
+        let remaining = [&records[..2], &records[3..]].concat();
+        let after = keys.map(|key| target(&remaining, key)).collect::<Vec<_>>();
+        let moved = before.iter().zip(&after).filter(|(a, b)| a != b).count();
+        let on_removed = before.iter().filter(|uri| *uri == removed).count();
+        // Only the keys routed to the removed target move, ~1/5 of them
+        assert_eq!(moved, on_removed);
+        assert!((100..300).contains(&moved), "{moved} keys moved");
+    }
+
+    #[test]
+    fn consistent_hash_honors_weights_and_priorities() {
+        let mut records = weighted_records(&[1, 3, 0, 100]);
+        records[3].priority = 1;
+        let mut counts = [0; 4];
+        for key in 0..4000_u32 {
+            let order = ConsistentHash.order_by_key(&records, hash(0, &key.to_be_bytes()));
+            let order = order.collect::<Vec<_>>();
+            assert_eq!(order[3], 3);
+            counts[order[0]] += 1;
+        }
+        // Weights 1 and 3 get ~1000 and ~3000 keys, and weight 0 almost none
+        assert!((800..1200).contains(&counts[0]), "{counts:?}");
+        assert!((2800..3200).contains(&counts[1]), "{counts:?}");
+        assert!(counts[2] < 20, "{counts:?}");
+    }
+
+    #[tokio::test]
+    async fn outlier_ejection_ejects_failing_targets() {
+        use crate::{
+            Execution,
+            resolver::manual::{StaticResolver, StaticSrvRecord},
+        };
+        let records = StaticSrvRecord::numbered(4);
+        let policy = OutlierEjection::new()
+            .consecutive_failures(2)
+            .max_ejected_percent(50);
+        let client = SrvClient::<_>::new_with_static_resolver(StaticResolver::new(records));
+        let client = client.policy(policy);
+        let mut tried = Vec::new();
+        for _ in 0..6 {
+            let res = client
+                .execute(Execution::Serial, |uri| {
+                    tried.push(uri.host().unwrap().to_owned());
+                    async move {
+                        match uri.host() {
+                            Some("server0.example.com" | "server1.example.com") => {
+                                Err(std::io::Error::other("unavailable"))
+                            }
+                            _ => Ok(()),
+                        }
+                    }
+                })
+                .await;
+            assert!(matches!(res, Ok(Ok(()))));
+        }
+        // Both failing targets are ejected after 2 failures (which lasts across
+        // cache refreshes, performed for every execution with a static resolver)
+        let tried = tried.iter().filter(|host| *host != "server2.example.com");
+        assert_eq!(tried.count(), 4);
+    }
+
+    #[test]
+    fn outlier_ejection_is_capped_and_expires() {
+        let policy = OutlierEjection::new()
+            .consecutive_failures(1)
+            .ejection(Duration::from_secs(10), Duration::from_secs(10))
+            .max_ejected_percent(60);
+        let records = weighted_records(&[0, 0, 0, 0]);
+        policy.lock_state().targets = records.len();
+        let ordered = || {
+            let mut order = policy.order(&records).collect::<Vec<_>>();
+            order.sort_unstable();
+            order
+        };
+        let failure = AttemptRecord::new(AttemptStatus::Failed, Duration::ZERO, 0, None);
+        for record in &records {
+            policy.note_failure(&record.uri, &failure);
+        }
+        // Only 2 of the 4 targets (60%, rounded down) are ejected
+        assert_eq!(ordered(), [2, 3]);
+        clock::advance(Duration::from_secs(9));
+        assert_eq!(ordered(), [2, 3]);
+        clock::advance(Duration::from_secs(1));
+        assert_eq!(ordered(), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn outlier_ejection_saturates_instead_of_overflowing() {
+        let policy = OutlierEjection::new()
+            .consecutive_failures(1)
+            .ejection(Duration::MAX, Duration::MAX)
+            .max_ejected_percent(50);
+        let records = weighted_records(&[0, 0]);
+        policy.lock_state().targets = records.len();
+        let failure = AttemptRecord::new(AttemptStatus::Failed, Duration::ZERO, 0, None);
+        policy.note_failure(&records[0].uri, &failure);
+        assert_eq!(policy.order(&records).collect::<Vec<_>>(), [1]);
+    }
+
+    #[test]
+    fn outlier_ejection_orders_ejected_targets_as_last_resort() {
+        let policy = OutlierEjection::new()
+            .consecutive_failures(1)
+            .max_ejected_percent(100);
+        let records = weighted_records(&[0, 0]);
+        policy.lock_state().targets = records.len();
+        let failure = AttemptRecord::new(AttemptStatus::Failed, Duration::ZERO, 0, None);
+        policy.note_failure(&records[0].uri, &failure);
+        assert_eq!(policy.order(&records).collect::<Vec<_>>(), [1]);
+        policy.note_failure(&records[1].uri, &failure);
+        let mut order = policy.order(&records).collect::<Vec<_>>();
+        order.sort_unstable();
+        assert_eq!(order, [0, 1]);
+    }
+
+    #[test]
+    fn least_loaded_prefers_idle_targets() {
+        let policy = LeastLoaded::default();
+        let mut records = weighted_records(&[1, 1, 5, 1]);
+        records[3].priority = 1;
+        let busy = InFlight::start(&policy, records[2].uri.clone(), 0);
+        let _also_busy = InFlight::start(&policy, records[0].uri.clone(), 0);
+        let _busier = InFlight::start(&policy, records[0].uri.clone(), 0);
+        assert_eq!(policy.order(&records).collect::<Vec<_>>(), [1, 2, 0, 3]);
+        drop(busy);
+        assert_eq!(policy.in_flight(&records[2].uri), 0);
+        // The heaviest target is preferred among idle targets
+        assert_eq!(policy.order(&records).collect::<Vec<_>>(), [2, 1, 0, 3]);
+    }
+
+    #[test]
+    fn peak_ewma_prefers_fast_targets() {
+        let policy = PeakEwma::new().decay(Duration::from_millis(1));
+        let records = weighted_records(&[0, 0, 0]);
+        let ms = Duration::from_millis;
+        let success = |millis| AttemptRecord::new(AttemptStatus::Succeeded, ms(millis), 0, None);
+        let failure = |millis| AttemptRecord::new(AttemptStatus::Failed, ms(millis), 0, None);
+        policy.note_success(&records[0].uri, &success(100));
+        policy.note_success(&records[1].uri, &success(10));
+        policy.note_failure(&records[2].uri, &failure(1));
+        assert_eq!(policy.order(&records).collect::<Vec<_>>(), [1, 0, 2]);
+        // A peak is followed immediately, while lower durations decay in
+        policy.note_success(&records[1].uri, &success(500));
+        assert_eq!(policy.estimate(&records[1].uri), Some(ms(500)));
+        assert_eq!(policy.order(&records).collect::<Vec<_>>(), [0, 1, 2]);
+        clock::advance(ms(20));
+        policy.note_success(&records[1].uri, &success(10));
+        assert!(policy.estimate(&records[1].uri).unwrap() < ms(20));
+        // Executions in flight add to a target's cost
+        let _in_flight = InFlight::start(&policy, records[1].uri.clone(), 0);
+        let _in_flight = InFlight::start(&policy, records[1].uri.clone(), 0);
+        policy.note_success(&records[0].uri, &success(15));
+        assert_eq!(policy.order(&records).next(), Some(0));
+    }
+
+    #[test]
+    fn peak_ewma_counts_cancellations_as_peaks() {
+        let policy = PeakEwma::new();
+        let records = weighted_records(&[0]);
+        let uri = &records[0].uri;
+        let ms = Duration::from_millis;
+        let record = |status, millis| AttemptRecord::new(status, ms(millis), 0, None);
+        policy.note_cancelled(uri, &record(AttemptStatus::Cancelled, 50));
+        assert_eq!(policy.estimate(uri), Some(ms(50)));
+        policy.note_success(uri, &record(AttemptStatus::Succeeded, 10));
+        let estimate = policy.estimate(uri).unwrap();
+        // Cancellations shorter than the estimate say nothing about latency
+        policy.note_cancelled(uri, &record(AttemptStatus::Cancelled, 1));
+        assert_eq!(policy.estimate(uri), Some(estimate));
+        policy.note_cancelled(uri, &record(AttemptStatus::Cancelled, 200));
+        assert_eq!(policy.estimate(uri), Some(ms(200)));
+    }
+
+    #[test]
+    fn peak_ewma_spreads_load_over_unused_targets() {
+        let policy = PeakEwma::new();
+        let records = weighted_records(&[0, 0, 0]);
+        let _in_flight = InFlight::start(&policy, records[0].uri.clone(), 0);
+        let _in_flight = InFlight::start(&policy, records[1].uri.clone(), 0);
+        let _in_flight = InFlight::start(&policy, records[1].uri.clone(), 0);
+        // None of the targets has finished an execution, so all cost nothing
+        for _ in 0..10 {
+            assert_eq!(policy.order(&records).collect::<Vec<_>>(), [2, 0, 1]);
+        }
+    }
+
+    #[tokio::test]
+    async fn attempts_are_recorded() {
+        use crate::{
+            Classified, Execution, Outcome,
+            resolver::manual::{StaticResolver, StaticSrvRecord},
+        };
+        use futures_util::future;
+
+        type Noted = (Option<u16>, AttemptStatus, usize, Option<&'static str>);
+        #[derive(Default)]
+        struct Recording(Mutex<Vec<Noted>>);
+        impl Recording {
+            fn note(&self, uri: &Uri, record: &AttemptRecord) {
+                let noted = (
+                    uri.port_u16(),
+                    record.status(),
+                    record.attempt(),
+                    record.error_class(),
+                );
+                self.0.lock().unwrap().push(noted);
+            }
+        }
+        #[async_trait]
+        impl Policy for Recording {
+            type CacheItem = ParsedRecord;
+            type Ordering = <Vec<usize> as IntoIterator>::IntoIter;
+            async fn refresh_cache<Resolver: SrvResolver, P: Policy>(
+                &self,
+                client: &SrvClient<Resolver, P>,
+            ) -> Result<Cache<Self::CacheItem>, Error<Resolver::Error>> {
+                ParsedRecord::refresh_cache(client).await
+            }
+            fn order(&self, items: &[ParsedRecord]) -> Self::Ordering {
+                let mut order = (0..items.len()).collect::<Vec<_>>();
+                order.sort_by_key(|&idx| items[idx].uri.port_u16());
+                order.into_iter()
+            }
+            fn cache_item_to_uri(item: &Self::CacheItem) -> &Uri {
+                &item.uri
+            }
+            fn note_success(&self, uri: &Uri, record: &AttemptRecord) {
+                self.note(uri, record);
+            }
+            fn note_failure(&self, uri: &Uri, record: &AttemptRecord) {
+                self.note(uri, record);
+            }
+            fn note_cancelled(&self, uri: &Uri, record: &AttemptRecord) {
+                self.note(uri, record);
+            }
+        }
+
+        let records = (0..3).map(|idx| StaticSrvRecord {
+            target: "server.example.com".to_owned(),
+            port: 8080 + idx,
+            priority: 0,
+            weight: 0,
+        });
+        let client = SrvClient::<_>::new_with_static_resolver(StaticResolver::new(records))
+            .policy(Recording::default());
+        let mode = Execution::Hedged {
+            delay: Duration::from_millis(10),
+        };
+        let res = client
+            .execute(mode, |candidate| async move {
+                let outcome = match candidate.port_u16() {
+                    Some(8080) => future::pending().await,
+                    Some(8081) => Outcome::Failure(std::io::Error::other("too many requests")),
+                    _ => Outcome::Success(()),
+                };
+                Classified {
+                    outcome,
+                    class: "overloaded",
+                }
+            })
+            .await;
+        assert!(matches!(res, Ok(Ok(()))));
+        // The hanging attempt was cancelled once a concurrent attempt succeeded
+        let noted = client.policy.0.lock().unwrap().clone();
+        assert_eq!(
+            noted,
+            [
+                (Some(8081), AttemptStatus::Failed, 1, Some("overloaded")),
+                (Some(8082), AttemptStatus::Succeeded, 2, None),
+                (Some(8080), AttemptStatus::Cancelled, 0, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn p2c_prefers_less_loaded_of_two() {
+        let policy = P2C::new();
+        let mut records = weighted_records(&[1, 1, 0]);
+        records[2].priority = 1;
+        let _busy = InFlight::start(&policy, records[0].uri.clone(), 0);
+        for _ in 0..10 {
+            assert_eq!(policy.order(&records).collect::<Vec<_>>(), [1, 0, 2]);
+        }
+    }
+
+    #[test]
+    fn p2c_counts_cancellations_as_slow() {
+        let policy = P2C::new();
+        let records = weighted_records(&[1, 1]);
+        let cancelled =
+            AttemptRecord::new(AttemptStatus::Cancelled, Duration::from_secs(1), 0, None);
+        policy.note_cancelled(&records[0].uri, &cancelled);
+        for _ in 0..10 {
+            assert_eq!(policy.order(&records).collect::<Vec<_>>(), [1, 0]);
+        }
+    }
+
+    #[test]
+    fn p2c_saturates_instead_of_overflowing() {
+        let policy = P2C::new().unhealthy_for(Duration::MAX);
+        let records = weighted_records(&[1, 1]);
+        let failure = AttemptRecord::new(AttemptStatus::Failed, Duration::ZERO, 0, None);
+        policy.note_failure(&records[0].uri, &failure);
+        for _ in 0..10 {
+            assert_eq!(policy.order(&records).next(), Some(1));
+        }
+    }
+
+    #[test]
+    fn p2c_samples_healthy_priority_by_weight() {
+        let policy = P2C::new();
+        let mut records = weighted_records(&[1, 0, 3, 1, 0]);
+        records[3].priority = 1;
+        records[4].priority = 1;
+        let first = |policy: &P2C| {
+            let mut counts = [0; 5];
+            for _ in 0..1000 {
+                let order = policy.order(&records).collect::<Vec<_>>();
+                let mut sorted = order.clone();
+                sorted.sort_unstable();
+                assert_eq!(sorted, [0, 1, 2, 3, 4]);
+                counts[order[0]] += 1;
+            }
+            counts
+        };
+        // Targets of weight zero are sampled only once the others are sampled
+        let counts = first(&policy);
+        assert_eq!(counts[1] + counts[3] + counts[4], 0);
+        assert!(counts[0] > 100 && counts[2] > 100, "{counts:?}");
+        // Failing targets are passed over until no target of their priority is healthy
+        let failure = AttemptRecord::new(AttemptStatus::Failed, Duration::ZERO, 0, None);
+        policy.note_failure(&records[2].uri, &failure);
+        let counts = first(&policy);
+        assert_eq!(counts[0], 1000);
+        policy.note_failure(&records[0].uri, &failure);
+        assert_eq!(first(&policy)[1], 1000);
+        policy.note_failure(&records[1].uri, &failure);
+        assert_eq!(first(&policy)[3], 1000);
+    }
+
+    #[test]
+    fn p2c_orders_fallbacks_by_health_and_weight() {
+        let policy = P2C::new();
+        let mut records = weighted_records(&[1, 1, 1, 10, 1]);
+        for record in &mut records[2..] {
+            record.priority = 1;
+        }
+        let failure = AttemptRecord::new(AttemptStatus::Failed, Duration::ZERO, 0, None);
+        policy.note_failure(&records[2].uri, &failure);
+        let mut heaviest_first = 0;
+        for _ in 0..1000 {
+            let order = policy.order(&records).collect::<Vec<_>>();
+            // The unhealthy fallback is tried last
+            assert_eq!(order[4], 2, "{order:?}");
+            heaviest_first += usize::from(order[2] == 3);
+        }
+        // Healthy fallbacks are selected by weight, i.e. ~909 times out of 1000
+        assert!(heaviest_first > 800, "{heaviest_first}");
+    }
+
+    #[test]
+    fn round_robin_interleaves_by_weight() {
+        let policy = RoundRobin::default();
+        let mut records = weighted_records(&[5, 1, 1, 9]);
+        records[3].priority = 1;
+        let order = (0..14)
+            .map(|_| policy.order(&records).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let first = order.iter().map(|order| order[0]).collect::<Vec<_>>();
+        assert_eq!(first, [0, 0, 1, 0, 2, 0, 0, 0, 0, 1, 0, 2, 0, 0]);
+        // Higher priority numbers follow only as fallbacks
+        assert!(order.iter().all(|order| order.len() == 4 && order[3] == 3));
+        // Targets of weight zero rotate equally among themselves
+        let records = weighted_records(&[0, 0]);
+        let first = (0..4)
+            .map(|_| policy.order(&records).next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(first, [0, 1, 0, 1]);
+    }
+
+    #[tokio::test]
+    async fn round_robin_rotation_survives_refreshes() {
+        use crate::resolver::manual::{StaticResolver, StaticSrvRecord};
+        let records = [3, 1]
+            .into_iter()
+            .enumerate()
+            .map(|(idx, weight)| StaticSrvRecord {
+                target: format!("server{idx}.example.com"),
+                port: 8080,
+                priority: 0,
+                weight,
+            });
+        let client =
+            SrvClient::<_, RoundRobin>::new_with_static_resolver(StaticResolver::new(records));
+        let mut selected = Vec::new();
+        for _ in 0..8 {
+            let cache = client.refresh_cache().await.unwrap();
+            let first = client.policy.order(cache.items()).next().unwrap();
+            selected.push(cache.items()[first].uri.host().unwrap().to_owned());
+        }
+        let expected =
+            ["server0", "server0", "server1", "server0"].map(|host| format!("{host}.example.com"));
+        assert_eq!(selected[..4], expected);
+        assert_eq!(selected[4..], expected);
+    }
+
+    #[test]
+    fn affinity_fails_back_after_hold_down() {
+        let mut records = weighted_records(&[0, 0]);
+        records[1].priority = 1;
+        let (primary, backup) = (&records[0].uri, &records[1].uri);
+        let success = AttemptRecord::new(AttemptStatus::Succeeded, Duration::ZERO, 0, None);
+        let order = |policy: &Affinity| policy.order(&records).collect::<Vec<_>>();
+        let hold_down = Duration::from_secs(60);
+
+        let sticky = Affinity::<Rfc2782>::default();
+        let policy = Affinity::<Rfc2782>::default().failback(hold_down);
+        for policy in [&sticky, &policy] {
+            policy.note_success(backup, &success);
+            assert_eq!(order(policy), [1, 0]);
+        }
+        clock::advance(hold_down);
+        assert_eq!(order(&sticky), [1, 0]);
+        // The primary is probed once the backup is held down
+        assert_eq!(order(&policy), [0, 1]);
+        // The backup is preferred for another period if the primary still fails
+        policy.note_success(backup, &success);
+        assert_eq!(order(&policy), [1, 0]);
+        clock::advance(hold_down);
+        policy.note_success(primary, &success);
+        clock::advance(hold_down);
+        // The primary is never held down
+        assert_eq!(order(&policy), [0, 1]);
+    }
+
+    #[test]
+    fn affinity_expires_after_ttl() {
+        let records = weighted_records(&[1, 1]);
+        let success = AttemptRecord::new(AttemptStatus::Succeeded, Duration::ZERO, 0, None);
+        let ttl = Duration::from_secs(60);
+        let policy = Affinity::new(LeastLoaded::default()).ttl(ttl);
+        policy.note_success(&records[1].uri, &success);
+        // The preferred target is busier, so it is only ordered first for affinity
+        let _busy = InFlight::start(&policy, records[1].uri.clone(), 0);
+        assert_eq!(policy.order(&records).next(), Some(1));
+        clock::advance(ttl);
+        assert_eq!(policy.order(&records).next(), Some(0));
+    }
+
+    #[test]
+    fn affinity_drops_failing_target() {
+        let mut records = weighted_records(&[0, 0, 0]);
+        for (priority, record) in (0..).zip(&mut records) {
+            record.priority = priority;
+        }
+        let success = AttemptRecord::new(AttemptStatus::Succeeded, Duration::ZERO, 0, None);
+        let failure = AttemptRecord::new(AttemptStatus::Failed, Duration::ZERO, 0, None);
+        let policy = Affinity::<Rfc2782>::default();
+        policy.note_success(&records[2].uri, &success);
+        assert_eq!(policy.order(&records).next(), Some(2));
+        // Failures of other targets keep the preference
+        policy.note_failure(&records[1].uri, &failure);
+        assert_eq!(policy.order(&records).next(), Some(2));
+        policy.note_failure(&records[2].uri, &failure);
+        assert_eq!(policy.order(&records).collect::<Vec<_>>(), [0, 1, 2]);
+    }
 
-    for _ in 0..5 {
-        ordered(Rfc2782.order(&cache));
+    #[test]
+    fn affinity_skips_targets_left_out_by_inner_policy() {
+        let records = weighted_records(&[0, 0, 0]);
+        let success = AttemptRecord::new(AttemptStatus::Succeeded, Duration::ZERO, 0, None);
+        let failure = AttemptRecord::new(AttemptStatus::Failed, Duration::ZERO, 0, None);
+        let policy = Affinity::new(OutlierEjection::new().consecutive_failures(1));
+        policy.inner().lock_state().targets = records.len();
+        policy.note_success(&records[2].uri, &success);
+        assert_eq!(policy.order(&records).next(), Some(2));
+        // The preferred target is ejected without its failure reaching affinity
+        policy.inner().note_failure(&records[2].uri, &failure);
+        let order = policy.order(&records).collect::<Vec<_>>();
+        assert_eq!(order.len(), 2);
+        assert!(!order.contains(&2), "{order:?}");
     }
 }
@@ -0,0 +1,401 @@
+//! Pools of connections to SRV targets.
+
+use super::{
+    AttemptContext, AttemptError, Cache, CallOptions, Error, Execution, SrvClient, policy,
+};
+use crate::resolver::SrvResolver;
+use async_trait::async_trait;
+use http::Uri;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Debug},
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex, MutexGuard, PoisonError, Weak},
+};
+
+/// Represents the ability to create and check connections to SRV targets
+/// for a [`SrvPool`].
+#[async_trait]
+pub trait Manager: Send + Sync {
+    /// Connections to SRV targets.
+    type Connection: Send;
+
+    /// Errors encountered while connecting to SRV targets.
+    type Error: std::error::Error;
+
+    /// Opens a new connection to a SRV target.
+    async fn connect(&self, uri: &Uri) -> Result<Self::Connection, Self::Error>;
+
+    /// Checks that an idle connection is still usable before it is reused.
+    /// Connections that fail the check are dropped.
+    async fn check(&self, _connection: &mut Self::Connection) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Pool of long-lived connections to the SRV targets of a [`SrvClient`].
+///
+/// Connections are taken from the pool with [`SrvPool::get`], which tries
+/// targets in the order determined by the client's [`Policy`] (reusing an idle
+/// connection to a target if there is one), and return to the pool when
+/// dropped. Idle connections to targets that are no longer among the client's
+/// SRV targets are dropped as soon as the pool is used after its cache is
+/// refreshed.
+///
+/// # Examples
+///
+/// ```
+/// use async_trait::async_trait;
+/// use srv_rs::{Manager, SrvClient, SrvPool};
+/// use srv_rs::resolver::manual::StaticResolver;
+///
+/// struct Connector;
+///
+/// #[async_trait]
+/// impl Manager for Connector {
+///     type Connection = String;
+///     type Error = std::io::Error;
+///
+///     async fn connect(&self, uri: &http::Uri) -> Result<String, std::io::Error> {
+///         // e.g. open a database or gRPC connection here
+///         Ok(uri.to_string())
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let resolver = StaticResolver::new_from_single_target("db.example.com", 5432);
+/// let client: SrvClient<_> = SrvClient::new_with_static_resolver(resolver);
+/// let pool = SrvPool::new(client, Connector);
+/// let connection = pool.get().await.unwrap().unwrap();
+/// assert_eq!(*connection, "https://db.example.com:5432/");
+/// # }
+/// ```
+///
+/// [`Policy`]: policy::Policy
+pub struct SrvPool<Resolver, M: Manager, Policy: policy::Policy = policy::Affinity> {
+    client: SrvClient<Resolver, Policy>,
+    manager: M,
+    max_idle_per_target: usize,
+    state: Mutex<PoolState<M::Connection, Policy::CacheItem>>,
+}
+
+/// Idle connections of a [`SrvPool`], along with the SRV targets they may be
+/// kept for.
+struct PoolState<Connection, CacheItem> {
+    /// Client cache the targets were last taken from.
+    cache: Weak<Cache<CacheItem>>,
+    targets: HashSet<Uri>,
+    idle: HashMap<Uri, Vec<Connection>>,
+}
+
+impl<Connection, CacheItem> PoolState<Connection, CacheItem> {
+    /// Takes the targets from `cache` if they were last taken from another
+    /// cache, dropping the idle connections to targets it no longer contains.
+    fn update_targets(&mut self, cache: &Arc<Cache<CacheItem>>, uri: fn(&CacheItem) -> &Uri) {
+        let last = self.cache.upgrade();
+        if last.is_some_and(|last| Arc::ptr_eq(&last, cache)) {
+            return;
+        }
+        self.cache = Arc::downgrade(cache);
+        self.targets = cache.items().iter().map(|item| uri(item).clone()).collect();
+        let targets = &self.targets;
+        self.idle.retain(|uri, _| targets.contains(uri));
+    }
+}
+
+impl<Resolver, M: Manager, Policy: policy::Policy> SrvPool<Resolver, M, Policy> {
+    /// Creates a new pool of connections to the SRV targets of `client`,
+    /// managed by `manager`.
+    pub fn new(client: SrvClient<Resolver, Policy>, manager: M) -> Self {
+        Self {
+            client,
+            manager,
+            max_idle_per_target: 8,
+            state: Mutex::new(PoolState {
+                cache: Weak::new(),
+                targets: HashSet::new(),
+                idle: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Sets the maximum number of idle connections kept for each SRV target.
+    /// Connections returned to a target with as many idle connections are
+    /// dropped. Defaults to 8.
+    #[must_use]
+    pub fn max_idle_per_target(self, max_idle_per_target: usize) -> Self {
+        Self {
+            max_idle_per_target,
+            ..self
+        }
+    }
+
+    /// Gets the client locating a pool's SRV targets.
+    pub const fn client(&self) -> &SrvClient<Resolver, Policy> {
+        &self.client
+    }
+
+    /// Gets the manager of a pool's connections.
+    pub const fn manager(&self) -> &M {
+        &self.manager
+    }
+
+    /// Gets the number of idle connections held by a pool.
+    pub fn idle(&self) -> usize {
+        self.lock_state().idle.values().map(Vec::len).sum()
+    }
+
+    /// Locks the state of a pool, first bringing its targets up to date with
+    /// the client's current cache.
+    fn lock_state(&self) -> MutexGuard<'_, PoolState<M::Connection, Policy::CacheItem>> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.update_targets(&self.client.cache.load_full(), Policy::cache_item_to_uri);
+        state
+    }
+
+    /// Takes an idle connection to a SRV target out of a pool.
+    fn take_idle(&self, uri: &Uri) -> Option<M::Connection> {
+        self.lock_state().idle.get_mut(uri)?.pop()
+    }
+
+    /// Returns a connection to a SRV target to a pool, unless the target has
+    /// since been removed or already has the maximum number of idle connections.
+    fn put_idle(&self, uri: Uri, connection: M::Connection) {
+        let mut state = self.lock_state();
+        if !state.targets.contains(&uri) {
+            return;
+        }
+        let idle = state.idle.entry(uri).or_default();
+        if idle.len() < self.max_idle_per_target {
+            idle.push(connection);
+        }
+        drop(state);
+    }
+}
+
+impl<Resolver: SrvResolver, M: Manager, Policy: policy::Policy> SrvPool<Resolver, M, Policy> {
+    /// Gets a connection to one of a pool's SRV targets, trying targets one
+    /// at a time in the order determined by the client's policy. An idle
+    /// connection to a target is reused if it passes [`Manager::check`], and
+    /// a new one is opened otherwise. Targets that cannot be connected to are
+    /// noted as failures by the client's policy. Each round of attempts is
+    /// made on a single snapshot of the client's targets, whose removed
+    /// targets' idle connections are dropped first.
+    ///
+    /// The connection returns to the pool when dropped.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::Lookup`] if DNS resolution fails
+    /// - [`Error::RecordParsing`] if SRV records cannot be parsed into valid URIs
+    /// - [`Error::NoTargets`] if there are no SRV targets available
+    pub async fn get(
+        &self,
+    ) -> Result<
+        Result<Pooled<'_, Resolver, M, Policy>, AttemptError<M::Error>>,
+        Error<Resolver::Error>,
+    > {
+        self.client.check_path_prefix()?;
+        let res = self
+            .client
+            .execute_projected(
+                Execution::Serial,
+                CallOptions::new(),
+                AttemptContext::into_uri,
+                |uri| self.checkout(uri),
+                |cache| {
+                    let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+                    state.update_targets(cache, Policy::cache_item_to_uri);
+                },
+            )
+            .await?;
+        Ok(res.map(|(uri, connection)| Pooled {
+            pool: self,
+            uri,
+            connection: Some(connection),
+        }))
+    }
+
+    /// Checks out a connection to a SRV target, reusing an idle one if possible.
+    async fn checkout(&self, uri: Uri) -> Result<(Uri, M::Connection), M::Error> {
+        while let Some(mut connection) = self.take_idle(&uri) {
+            if self.manager.check(&mut connection).await.is_ok() {
+                return Ok((uri, connection));
+            }
+        }
+        let connection = self.manager.connect(&uri).await?;
+        Ok((uri, connection))
+    }
+}
+
+impl<Resolver: Debug, M: Manager + Debug, Policy: policy::Policy + Debug> Debug
+    for SrvPool<Resolver, M, Policy>
+where
+    SrvClient<Resolver, Policy>: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SrvPool")
+            .field("client", &self.client)
+            .field("manager", &self.manager)
+            .field("max_idle_per_target", &self.max_idle_per_target)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Connection checked out of a [`SrvPool`], which returns to the pool when
+/// dropped.
+pub struct Pooled<'a, Resolver, M: Manager, Policy: policy::Policy = policy::Affinity> {
+    pool: &'a SrvPool<Resolver, M, Policy>,
+    uri: Uri,
+    connection: Option<M::Connection>,
+}
+
+impl<Resolver, M: Manager, Policy: policy::Policy> Pooled<'_, Resolver, M, Policy> {
+    /// Gets the URI of the SRV target a connection is open to.
+    pub const fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// Takes a connection out of its pool for good, e.g. because it is broken
+    /// and should not be reused.
+    pub fn detach(mut self) -> M::Connection {
+        let connection = self.connection.take();
+        connection.unwrap_or_else(|| {
+            unreachable!("pooled connection is only taken when detached or dropped")
+        })
+    }
+}
+
+impl<Resolver, M: Manager, Policy: policy::Policy> Deref for Pooled<'_, Resolver, M, Policy> {
+    type Target = M::Connection;
+
+    fn deref(&self) -> &Self::Target {
+        self.connection
+            .as_ref()
+            .expect("pooled connection is only taken when detached or dropped")
+    }
+}
+
+impl<Resolver, M: Manager, Policy: policy::Policy> DerefMut for Pooled<'_, Resolver, M, Policy> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.connection
+            .as_mut()
+            .expect("pooled connection is only taken when detached or dropped")
+    }
+}
+
+impl<Resolver, M: Manager, Policy: policy::Policy> Drop for Pooled<'_, Resolver, M, Policy> {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            self.pool.put_idle(self.uri.clone(), connection);
+        }
+    }
+}
+
+impl<Resolver, M: Manager, Policy: policy::Policy> Debug for Pooled<'_, Resolver, M, Policy>
+where
+    M::Connection: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pooled")
+            .field("uri", &self.uri)
+            .field("connection", &self.connection)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::manual::{StaticResolver, StaticSrvRecord};
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Instant,
+    };
+
+    /// Manager whose connections are numbered in the order they are opened.
+    #[derive(Default)]
+    struct Counter {
+        connected: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Manager for Counter {
+        type Connection = usize;
+        type Error = std::io::Error;
+
+        async fn connect(&self, _uri: &Uri) -> Result<usize, std::io::Error> {
+            Ok(self.connected.fetch_add(1, Ordering::SeqCst))
+        }
+    }
+
+    /// Resolver whose set of records can be changed.
+    struct Changing(Mutex<Vec<StaticSrvRecord>>);
+
+    #[async_trait]
+    impl SrvResolver for Changing {
+        type Record = StaticSrvRecord;
+        type Error = std::convert::Infallible;
+
+        async fn get_srv_records_unordered(
+            &self,
+            _srv: &str,
+        ) -> Result<(Vec<Self::Record>, Instant), Self::Error> {
+            Ok((self.0.lock().unwrap().clone(), Instant::now()))
+        }
+    }
+
+    #[tokio::test]
+    async fn connections_are_reused() {
+        let resolver = StaticResolver::new_from_single_target("server.example.com", 8080);
+        let client: SrvClient<_> = SrvClient::new_with_static_resolver(resolver);
+        let pool = SrvPool::new(client, Counter::default());
+        let first = pool.get().await.unwrap().unwrap();
+        let second = pool.get().await.unwrap().unwrap();
+        assert_eq!((*first, *second), (0, 1));
+        drop(first);
+        assert_eq!(pool.idle(), 1);
+        assert_eq!(*pool.get().await.unwrap().unwrap(), 0);
+        pool.get().await.unwrap().unwrap().detach();
+        assert_eq!(pool.idle(), 0);
+    }
+
+    #[tokio::test]
+    async fn removed_targets_are_dropped() {
+        let records = StaticSrvRecord::numbered(2);
+        let resolver = Changing(Mutex::new(vec![records[0].clone()]));
+        let client: SrvClient<_> = SrvClient::new_with_resolver("pool", resolver);
+        let pool = SrvPool::new(client, Counter::default());
+        let connection = pool.get().await.unwrap().unwrap();
+        assert_eq!(connection.uri().host(), Some("server0.example.com"));
+        drop(connection);
+        assert_eq!(pool.idle(), 1);
+
+        *pool.client().resolver.0.lock().unwrap() = vec![records[1].clone()];
+        let connection = pool.get().await.unwrap().unwrap();
+        assert_eq!(connection.uri().host(), Some("server1.example.com"));
+        assert_eq!(pool.idle(), 0);
+    }
+
+    #[tokio::test]
+    async fn removed_targets_are_dropped_after_any_refresh() {
+        let records = StaticSrvRecord::numbered(2);
+        let resolver = Changing(Mutex::new(vec![records[0].clone()]));
+        let client: SrvClient<_> = SrvClient::new_with_resolver("pool", resolver);
+        let pool = SrvPool::new(client, Counter::default());
+        drop(pool.get().await.unwrap().unwrap());
+        let connection = pool.get().await.unwrap().unwrap();
+        assert_eq!(pool.idle(), 0);
+
+        // The client's cache is refreshed by an operation other than the pool's
+        *pool.client().resolver.0.lock().unwrap() = vec![records[1].clone()];
+        let res = pool
+            .client()
+            .execute(Execution::Serial, |_| async { Ok::<_, std::io::Error>(()) })
+            .await;
+        assert!(matches!(res, Ok(Ok(()))));
+        drop(connection);
+        assert_eq!(pool.idle(), 0);
+    }
+}
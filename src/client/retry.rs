@@ -0,0 +1,127 @@
+//! Retry configurations for performing operations on SRV targets.
+
+use rand::Rng;
+use std::time::Duration;
+
+/// Retry configuration to use when every SRV target fails to perform an
+/// operation.
+///
+/// An operation is performed in *rounds*, each of which tries the targets in
+/// the order determined by a client's policy. If every target fails, another
+/// round is started after an exponentially increasing, randomly jittered
+/// backoff, until the configured number of rounds has been performed.
+///
+/// # Examples
+///
+/// ```
+/// use srv_rs::Retry;
+/// use std::time::Duration;
+/// let retry = Retry::new(3)
+///     .backoff(Duration::from_millis(50), Duration::from_secs(2))
+///     .refresh_cache(true);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Retry {
+    rounds: usize,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    refresh_cache: bool,
+}
+
+impl Retry {
+    /// Creates a retry configuration performing `rounds` rounds in total
+    /// (including the first). A value of zero is treated as one, i.e. no
+    /// retries.
+    #[must_use]
+    pub fn new(rounds: usize) -> Self {
+        Self {
+            rounds: rounds.max(1),
+            ..Self::default()
+        }
+    }
+
+    /// Sets the backoff before the first retry, which doubles for every
+    /// subsequent retry up to `max_backoff`. No retry is performed if an
+    /// operation's deadline would pass during its backoff.
+    #[must_use]
+    pub const fn backoff(self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        Self {
+            initial_backoff,
+            max_backoff,
+            ..self
+        }
+    }
+
+    /// Sets whether a client's cache of SRV targets is refreshed before each
+    /// retry, so that changes to the set of targets are picked up. If the
+    /// refresh fails, the previous round's targets are retried.
+    #[must_use]
+    pub const fn refresh_cache(self, refresh_cache: bool) -> Self {
+        Self {
+            refresh_cache,
+            ..self
+        }
+    }
+
+    /// Gets the total number of rounds to perform.
+    #[must_use]
+    pub const fn rounds(&self) -> usize {
+        self.rounds
+    }
+
+    /// Determines if a client's cache should be refreshed before a retry.
+    #[must_use]
+    pub const fn refreshes_cache(&self) -> bool {
+        self.refresh_cache
+    }
+
+    /// Picks the backoff to wait before the `retry`th retry (starting at
+    /// zero), uniformly at random between zero and its exponential bound.
+    pub(crate) fn backoff_before(&self, retry: usize) -> Duration {
+        let factor = 1_u32.checked_shl(retry.try_into().unwrap_or(u32::MAX));
+        let bound = factor
+            .and_then(|factor| self.initial_backoff.checked_mul(factor))
+            .map_or(self.max_backoff, |bound| bound.min(self.max_backoff))
+            // `mul_f64` panics if its result doesn't fit in a `Duration`
+            .min(Duration::from_secs(u64::MAX / 2));
+        bound.mul_f64(rand::rng().random::<f64>())
+    }
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            rounds: 1,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            refresh_cache: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_rounds_is_one() {
+        assert_eq!(Retry::new(0).rounds(), 1);
+        assert_eq!(Retry::default().rounds(), 1);
+    }
+
+    #[test]
+    fn backoff_is_bounded() {
+        let retry = Retry::new(10).backoff(Duration::from_millis(10), Duration::from_millis(50));
+        for (retry_idx, bound) in [(0, 10), (1, 20), (2, 40), (3, 50), (100, 50)] {
+            assert!(retry.backoff_before(retry_idx) <= Duration::from_millis(bound));
+        }
+    }
+
+    #[test]
+    fn huge_backoff_does_not_overflow() {
+        let retry = Retry::new(10).backoff(Duration::MAX, Duration::MAX);
+        for retry_idx in [0, 1, 100] {
+            retry.backoff_before(retry_idx);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Host/port targets of SRV records.
+
+use crate::SrvRecord;
+use http::Uri;
+use std::{
+    fmt::{self, Display},
+    io,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    vec,
+};
+
+/// Host and port of a SRV target, for operations on services that are not
+/// addressed by URIs (e.g. databases or raw TCP protocols).
+///
+/// A target implements [`ToSocketAddrs`], so it can be passed directly to
+/// e.g. [`std::net::TcpStream::connect`].
+///
+/// # Examples
+///
+/// ```
+/// use srv_rs::Target;
+/// let target = Target::new("server.example.com", 5432);
+/// assert_eq!(target.to_string(), "server.example.com:5432");
+/// assert_eq!(Target::new("::1", 5432).socket_addr(), Some("[::1]:5432".parse().unwrap()));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Target {
+    host: String,
+    port: u16,
+}
+
+impl Target {
+    /// Creates a target from a host (a domain name or IP address) and port.
+    pub fn new(host: impl Into<String>, port: u16) -> Self {
+        let host = host.into();
+        Self { host, port }
+    }
+
+    /// Creates a target from the target and port of a SRV record.
+    pub(crate) fn from_record(record: &impl SrvRecord) -> Self {
+        Self::from_authority(&record.target().to_string(), record.port())
+    }
+
+    /// Creates a target from the authority of a URI, as parsed from a SRV record.
+    pub(crate) fn from_uri(uri: &Uri) -> Self {
+        let host = uri.host().unwrap_or_default();
+        Self::from_authority(host, uri.port_u16().unwrap_or_default())
+    }
+
+    /// Creates a target from a host that may be an IPv6 address in brackets.
+    fn from_authority(host: &str, port: u16) -> Self {
+        let host = host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host);
+        Self::new(host, port)
+    }
+
+    /// Gets the host of a target.
+    #[must_use]
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Gets the port of a target.
+    #[must_use]
+    pub const fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Gets the socket address of a target if its host is an IP address
+    /// rather than a domain name.
+    #[must_use]
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        let ip: IpAddr = self.host.parse().ok()?;
+        Some(SocketAddr::new(ip, self.port))
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.socket_addr() {
+            Some(addr) => write!(f, "{addr}"),
+            None => write!(f, "{}:{}", self.host, self.port),
+        }
+    }
+}
+
+impl ToSocketAddrs for Target {
+    type Iter = vec::IntoIter<SocketAddr>;
+
+    fn to_socket_addrs(&self) -> io::Result<Self::Iter> {
+        (self.host.as_str(), self.port).to_socket_addrs()
+    }
+}
+
+impl From<&Uri> for Target {
+    fn from(uri: &Uri) -> Self {
+        Self::from_uri(uri)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::manual::StaticSrvRecord;
+
+    #[test]
+    fn from_uri() {
+        let uri: Uri = "https://server.example.com:8080/path".parse().unwrap();
+        assert_eq!(Target::from(&uri), Target::new("server.example.com", 8080));
+        let uri: Uri = "https://[::1]:8080/".parse().unwrap();
+        let target = Target::from(&uri);
+        assert_eq!(target.host(), "::1");
+        assert_eq!(target.socket_addr(), Some("[::1]:8080".parse().unwrap()));
+        assert_eq!(target.to_string(), "[::1]:8080");
+    }
+
+    #[test]
+    fn from_record() {
+        let record = StaticSrvRecord {
+            target: String::from("[::1]"),
+            port: 8080,
+            priority: 0,
+            weight: 0,
+        };
+        assert_eq!(Target::from_record(&record), Target::new("::1", 8080));
+        let record = StaticSrvRecord {
+            target: String::from("server.example.com"),
+            ..record
+        };
+        let target = Target::from_record(&record);
+        assert_eq!(target, Target::new("server.example.com", 8080));
+    }
+
+    #[test]
+    fn domain_has_no_socket_addr() {
+        assert_eq!(Target::new("server.example.com", 80).socket_addr(), None);
+    }
+}
@@ -65,14 +65,23 @@ The provided resolver backends are enabled by the following features:
 - `hickory` (via [`hickory_resolver::Resolver`])
 - `libresolv` (via [`LibResolv`])
 
+Both resolve SRV targets to IP addresses as well (see [`IpResolver`]), and the
+`tokio` feature enables [`SrvClient::connect_tcp`], which opens TCP connections
+to SRV targets.
+
 [`SrvResolver`]: resolver::SrvResolver
+[`IpResolver`]: resolver::IpResolver
 [`Policy`]: policy::Policy
 [`LibResolv`]: resolver::libresolv::LibResolv
 [`Resolver`]: hickory_resolver::Resolver
 */
 
 mod client;
-pub use client::{Error, Execution, SrvClient, policy};
+pub use client::{
+    AddressFamily, AttemptContext, AttemptError, AttemptErrors, CallOptions, Classified, Error,
+    Execution, IntoOutcome, Manager, Outcome, Pooled, QuorumErrors, Retry, SrvClient, SrvPool,
+    Target, blocking, policy,
+};
 
 mod record;
 pub use record::SrvRecord;
@@ -1,7 +1,7 @@
 //! SRV records.
 
 use http::uri::{PathAndQuery, Scheme, Uri};
-use rand::Rng;
+use rand::{Rng, seq::SliceRandom};
 use std::{cmp::Reverse, fmt::Display};
 
 /// Representation of types that contain the fields of a SRV record.
@@ -65,15 +65,140 @@ pub trait SrvRecord {
     }
 
     /// Generates a key to sort a SRV record by priority and weight per RFC 2782.
-    fn sort_key(&self, rng: impl Rng) -> (u16, Reverse<u32>) {
-        sort_key(self.priority(), self.weight(), rng)
+    /// This is an approximation of the RFC's weighted selection algorithm.
+    #[deprecated(note = "SRV records are now ordered by the RFC's exact weighted selection")]
+    fn sort_key(&self, mut rng: impl Rng) -> (u16, Reverse<u32>) {
+        // Sort ascending by priority, then descending (hence `Reverse`) by randomized weight
+        let rand = u32::from(rng.random::<u16>());
+        (self.priority(), Reverse(u32::from(self.weight()) * rand))
     }
 }
 
-/// Generates a key to sort a SRV record by priority and weight per RFC 2782.
-/// This is an approximation of the RFC's weighted selection algorithm.
-pub fn sort_key(priority: u16, weight: u16, mut rng: impl Rng) -> (u16, Reverse<u32>) {
-    // Sort ascending by priority, then descending (hence `Reverse`) by randomized weight
-    let rand = u32::from(rng.random::<u16>());
-    (priority, Reverse(u32::from(weight) * rand))
+/// Orders items by priority and weight per RFC 2782, given each item's
+/// priority and weight.
+///
+/// Items are ordered by ascending priority. Within a priority, items are
+/// selected one at a time by the RFC's running-sum algorithm: items of weight
+/// zero are placed first, a random number is chosen between zero and the sum of
+/// the remaining weights (inclusive), and the first item whose running sum of
+/// weights is at least that number is selected next.
+pub fn order_rfc2782<T>(
+    items: &mut [T],
+    priority_and_weight: impl Fn(&T) -> (u16, u16),
+    mut rng: impl Rng,
+) {
+    // The RFC leaves the order items are arranged in before selection open, so
+    // it is randomized to avoid favoring any item of weight zero
+    items.shuffle(&mut rng);
+    items.sort_by_key(|item| {
+        let (priority, weight) = priority_and_weight(item);
+        (priority, weight != 0)
+    });
+    let priority = |item: &T| priority_and_weight(item).0;
+    let weight = |item: &T| u32::from(priority_and_weight(item).1);
+    for tier in items.chunk_by_mut(|a, b| priority(a) == priority(b)) {
+        for start in 0..tier.len() {
+            let remaining = &mut tier[start..];
+            let total = remaining.iter().map(weight).sum::<u32>();
+            let point = rng.random_range(0..=total);
+            let mut running_sum = 0;
+            let selected = remaining
+                .iter()
+                .position(|item| {
+                    running_sum += weight(item);
+                    running_sum >= point
+                })
+                .unwrap_or_default();
+            // Keeps the order of the unselected items, with weight zero first
+            remaining[..=selected].rotate_right(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    /// Orders records of the given priorities and weights many times,
+    /// producing how often each record was ordered first.
+    fn first_frequencies(records: &[(u16, u16)]) -> Vec<f64> {
+        const SAMPLES: u32 = 100_000;
+        let mut rng = StdRng::seed_from_u64(2782);
+        let mut counts = vec![0_u32; records.len()];
+        for _ in 0..SAMPLES {
+            let mut order = (0..records.len()).collect::<Vec<_>>();
+            order_rfc2782(&mut order, |&idx| records[idx], &mut rng);
+            counts[order[0]] += 1;
+        }
+        counts
+            .into_iter()
+            .map(|count| f64::from(count) / f64::from(SAMPLES))
+            .collect()
+    }
+
+    fn assert_frequencies(records: &[(u16, u16)], expected: &[f64]) {
+        let frequencies = first_frequencies(records);
+        for (frequency, expected) in frequencies.iter().zip(expected) {
+            assert!(
+                (frequency - expected).abs() < 0.01,
+                "{frequencies:?} != {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn selection_follows_weights() {
+        // Selecting among weights summing to 4 draws a number from 0 to 4, so
+        // the record arranged first (either, at random) also takes the draw of 0
+        assert_frequencies(&[(0, 1), (0, 3)], &[1.5 / 5.0, 3.5 / 5.0]);
+        assert_frequencies(
+            &[(0, 10), (0, 20), (0, 70)],
+            &[
+                (10.0 + 1.0 / 3.0) / 101.0,
+                (20.0 + 1.0 / 3.0) / 101.0,
+                (70.0 + 1.0 / 3.0) / 101.0,
+            ],
+        );
+    }
+
+    #[test]
+    fn weight_zero_has_small_chance() {
+        // Records of weight zero are arranged first, so they take the draw of 0
+        assert_frequencies(
+            &[(0, 0), (0, 10), (0, 10)],
+            &[1.0 / 21.0, 10.0 / 21.0, 10.0 / 21.0],
+        );
+        assert_frequencies(
+            &[(0, 0), (0, 0), (0, 8)],
+            &[1.0 / 18.0, 1.0 / 18.0, 8.0 / 9.0],
+        );
+        assert_frequencies(&[(0, 0), (0, 0)], &[0.5, 0.5]);
+    }
+
+    #[test]
+    fn lower_priorities_come_first() {
+        let records = [(2, 100), (1, 0), (1, 5), (3, 1000), (2, 0)];
+        let mut rng = StdRng::seed_from_u64(2782);
+        for _ in 0..100 {
+            let mut order = (0..records.len()).collect::<Vec<_>>();
+            order_rfc2782(&mut order, |&idx| records[idx], &mut rng);
+            let priorities = order.iter().map(|&idx| records[idx].0).collect::<Vec<_>>();
+            assert_eq!(priorities, [1, 1, 2, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn later_selections_follow_weights() {
+        let records = [(0, 1), (0, 1), (0, 98)];
+        let mut rng = StdRng::seed_from_u64(2782);
+        let mut last = [0_u32; 3];
+        for _ in 0..10_000 {
+            let mut order = (0..records.len()).collect::<Vec<_>>();
+            order_rfc2782(&mut order, |&idx| records[idx], &mut rng);
+            last[order[2]] += 1;
+        }
+        // The heaviest record is almost always selected before the others
+        assert!(last[2] < 300, "{last:?}");
+    }
 }
@@ -1,12 +1,16 @@
 //! SRV resolver backed by [`hickory_resolver`].
 
-use super::SrvResolver;
-use crate::SrvRecord;
+use super::{IpResolver, SrvResolver};
+use crate::{AddressFamily, SrvRecord};
 use async_trait::async_trait;
+use futures_util::future::{self, OptionFuture};
 use hickory_resolver::{
-    Name, ResolveError, Resolver, name_server::ConnectionProvider, proto::rr::rdata::SRV,
+    Name, ResolveError, Resolver,
+    lookup::Lookup,
+    name_server::ConnectionProvider,
+    proto::rr::{RData, rdata::SRV},
 };
-use std::time::Instant;
+use std::{net::IpAddr, time::Instant};
 
 #[async_trait]
 impl<P> SrvResolver for Resolver<P>
@@ -26,6 +30,48 @@ where
     }
 }
 
+/// Resolves hosts by querying their A and AAAA records concurrently, so that
+/// addresses of both families are found even when a host has both.
+#[async_trait]
+impl<P> IpResolver for Resolver<P>
+where
+    P: ConnectionProvider,
+{
+    async fn get_ip_addrs(
+        &self,
+        host: &str,
+        family: AddressFamily,
+    ) -> Result<(Vec<IpAddr>, Instant), Self::Error> {
+        let v4 = family.includes_v4().then(|| self.ipv4_lookup(host));
+        let v6 = family.includes_v6().then(|| self.ipv6_lookup(host));
+        let (v4, v6) = future::join(OptionFuture::from(v4), OptionFuture::from(v6)).await;
+        let lookups = [
+            v4.map(|res| res.map(Lookup::from)),
+            v6.map(|res| res.map(Lookup::from)),
+        ];
+        let mut addrs = Vec::new();
+        let mut valid_until = None::<Instant>;
+        let mut no_records = None;
+        for lookup in lookups.into_iter().flatten() {
+            match lookup {
+                Ok(lookup) => {
+                    addrs.extend(lookup.iter().filter_map(RData::ip_addr));
+                    let until = lookup.valid_until();
+                    valid_until = Some(valid_until.map_or(until, |valid| valid.min(until)));
+                }
+                // A host without records of one family may have records of the other
+                Err(err) if err.is_no_records_found() => no_records = Some(err),
+                Err(err) => return Err(err),
+            }
+        }
+        match (valid_until, no_records) {
+            (Some(valid_until), _) => Ok((addrs, valid_until)),
+            (None, Some(err)) => Err(err),
+            (None, None) => Ok((addrs, Instant::now())),
+        }
+    }
+}
+
 #[allow(clippy::use_self)]
 impl SrvRecord for SRV {
     type Target = Name;
@@ -1,11 +1,16 @@
 //! SRV Resolver backed by `libresolv`.
 
-use super::SrvResolver;
-use crate::SrvRecord;
+use super::{IpResolver, SrvResolver};
+use crate::{AddressFamily, SrvRecord};
 use async_trait::async_trait;
-use resolv::{Record, Resolver};
+use resolv::{
+    Record, Resolver,
+    error::{Error as ResolvError, ResolutionError},
+    record::{A, AAAA, RecordData},
+};
 use std::{
     cell::RefCell,
+    net::IpAddr,
     time::{Duration, Instant},
 };
 
@@ -71,6 +76,55 @@ impl SrvResolver for LibResolv {
     }
 }
 
+/// Resolves hosts by querying their A and/or AAAA records, so that addresses
+/// are cached according to their TTLs.
+#[async_trait]
+impl IpResolver for LibResolv {
+    async fn get_ip_addrs(
+        &self,
+        host: &str,
+        family: AddressFamily,
+    ) -> Result<(Vec<IpAddr>, Instant), Self::Error> {
+        RESOLVER.with(|resolver| {
+            let mut resolver = resolver.borrow_mut();
+            let response_time = Instant::now();
+            let mut answers = Vec::new();
+            if family.includes_v4() {
+                answers.extend(search(&mut resolver, host, |record: A| {
+                    record.address.into()
+                })?);
+            }
+            if family.includes_v6() {
+                answers.extend(search(&mut resolver, host, |record: AAAA| {
+                    record.address.into()
+                })?);
+            }
+            let min_ttl = answers.iter().map(|(ttl, _)| *ttl).min();
+            let addrs = answers.into_iter().map(|(_, addr)| addr).collect();
+            Ok((addrs, response_time + min_ttl.unwrap_or(Duration::ZERO)))
+        })
+    }
+}
+
+/// Gets the addresses in a host's records of some type along with their TTLs,
+/// treating a host without records of the type as having no addresses.
+fn search<T: RecordData>(
+    resolver: &mut Resolver,
+    host: &str,
+    address: impl Fn(T) -> IpAddr,
+) -> Result<Vec<(Duration, IpAddr)>, LibResolvError> {
+    let mut response =
+        match resolver.search(host.as_bytes(), resolv::Class::IN, T::get_record_type()) {
+            Ok(response) => response,
+            Err(ResolvError::Resolver(ResolutionError::NoData)) => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+    Ok(response
+        .answers::<T>()
+        .map(|x| (Duration::from_secs(u64::from(x.ttl)), address(x.data)))
+        .collect())
+}
+
 /// Representation of SRV records used by [`LibResolv`].
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct LibResolvSrvRecord {
@@ -1,9 +1,9 @@
 //! Static SRV resolver that returns manually pre-configured records without DNS lookups.
 
-use super::SrvResolver;
-use crate::SrvRecord;
+use super::{IpResolver, SrvResolver};
+use crate::{AddressFamily, SrvRecord};
 use async_trait::async_trait;
-use std::{convert::Infallible, time::Instant};
+use std::{convert::Infallible, net::IpAddr, time::Instant};
 
 /// SRV resolver that returns a static, manually specified set of records without performing DNS lookups.
 ///
@@ -81,6 +81,20 @@ impl SrvResolver for StaticResolver {
     }
 }
 
+/// Resolves hosts without DNS lookups, so only targets that are IP addresses
+/// have addresses.
+#[async_trait]
+impl IpResolver for StaticResolver {
+    async fn get_ip_addrs(
+        &self,
+        host: &str,
+        _family: AddressFamily,
+    ) -> Result<(Vec<IpAddr>, Instant), Self::Error> {
+        let addrs = host.parse().into_iter().collect();
+        Ok((addrs, Instant::now()))
+    }
+}
+
 /// A manual SRV record with pre-configured target, port, priority, and weight.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct StaticSrvRecord {
@@ -114,6 +128,22 @@ impl SrvRecord for StaticSrvRecord {
     }
 }
 
+#[cfg(test)]
+impl StaticSrvRecord {
+    /// Creates `count` records of targets `server{idx}.example.com:8080`, each
+    /// of priority `idx` and weight 0.
+    pub(crate) fn numbered(count: u16) -> Vec<Self> {
+        (0..count)
+            .map(|idx| Self {
+                target: format!("server{idx}.example.com"),
+                port: 8080,
+                priority: idx,
+                weight: 0,
+            })
+            .collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
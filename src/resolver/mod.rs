@@ -1,9 +1,9 @@
 //! SRV resolvers.
 
-use crate::SrvRecord;
+use crate::{AddressFamily, SrvRecord};
 use async_trait::async_trait;
 use rand::Rng;
-use std::time::Instant;
+use std::{net::IpAddr, time::Instant};
 
 #[cfg(feature = "libresolv")]
 pub mod libresolv;
@@ -43,7 +43,20 @@ pub trait SrvResolver: Send + Sync {
     }
 
     /// Sorts SRV records by priority and weight per RFC 2782.
-    fn order_srv_records(records: &mut [Self::Record], mut rng: impl Rng) {
-        records.sort_by_cached_key(|record| record.sort_key(&mut rng));
+    fn order_srv_records(records: &mut [Self::Record], rng: impl Rng) {
+        crate::record::order_rfc2782(records, |record| (record.priority(), record.weight()), rng);
     }
 }
+
+/// Represents the ability to resolve the targets of SRV records to IP addresses.
+#[async_trait]
+pub trait IpResolver: SrvResolver {
+    /// Gets the addresses of a host in the given families, returning them
+    /// along with the time they're valid until. Families the preference
+    /// excludes need not be queried.
+    async fn get_ip_addrs(
+        &self,
+        host: &str,
+        family: AddressFamily,
+    ) -> Result<(Vec<IpAddr>, Instant), Self::Error>;
+}